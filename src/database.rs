@@ -1,5 +1,7 @@
 use async_trait::async_trait;
+use itertools::Itertools;
 use sqlite;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 const INIT_QUERY: &str = "
 PRAGMA synchronous = OFF;
@@ -8,6 +10,7 @@ CREATE TABLE IF NOT EXISTS Words(
     id INTEGER PRIMARY KEY,
     keyword VARCHAR(20),
     string VARCHAR(255),
+    class TEXT,
     UNIQUE(keyword,string)
     );
 
@@ -16,25 +19,201 @@ CREATE TABLE IF NOT EXISTS Occurrence (
     curr INT NOT NULL,
     next INT NOT NULL,
     occurrences INT,
+    weight REAL NOT NULL DEFAULT 0,
+    last_seen INT NOT NULL DEFAULT 0,
     UNIQUE(prev, curr, next)
     );
+
+CREATE TABLE IF NOT EXISTS NGram (
+    prefix TEXT NOT NULL,
+    suffix TEXT NOT NULL,
+    first INT NOT NULL,
+    last INT NOT NULL,
+    occurrences INT,
+    weight REAL NOT NULL DEFAULT 0,
+    last_seen INT NOT NULL DEFAULT 0,
+    UNIQUE(prefix, last)
+    );
+
+CREATE INDEX IF NOT EXISTS OccurrenceReverse ON Occurrence (curr, next);
+
+CREATE INDEX IF NOT EXISTS NGramSuffix ON NGram (suffix);
+
+CREATE TABLE IF NOT EXISTS Fingerprint (
+    hash INT PRIMARY KEY,
+    occurrences INT,
+    last_seen INT NOT NULL DEFAULT 0
+    ) WITHOUT ROWID;
+
+CREATE TABLE IF NOT EXISTS Line (
+    hash INT PRIMARY KEY,
+    occurrences INT,
+    last_seen INT NOT NULL DEFAULT 0
+    ) WITHOUT ROWID;
+
+CREATE TABLE IF NOT EXISTS Contribution (
+    user_id INT NOT NULL,
+    indices TEXT NOT NULL,
+    timestamp INT NOT NULL
+    );
+
+CREATE INDEX IF NOT EXISTS ContributionUser ON Contribution (user_id);
+
+CREATE TABLE IF NOT EXISTS Meta (
+    key TEXT PRIMARY KEY,
+    value INT
+    );
     ";
 
 const ADD_QUERY: &str = "
-    INSERT OR IGNORE INTO Words (id, keyword, string) VALUES(
+    INSERT INTO Words (id, keyword, string, class) VALUES(
         null,
         :keyword,
-        :string
-        );
+        :string,
+        :class
+        )
+    ON CONFLICT(keyword, string) DO UPDATE SET class = COALESCE(excluded.class, class)
+        WHERE class IS NOT COALESCE(excluded.class, class);
     ";
 
 const INCREMENT_QUERY: &str = "
-INSERT INTO Occurrence (prev, curr, next, occurrences) VALUES(:index1, :index2, :index3, 1)
-    ON CONFLICT(prev, curr, next) DO UPDATE SET occurrences = occurrences + 1;
+INSERT INTO Occurrence (prev, curr, next, occurrences, weight, last_seen)
+    VALUES(:index1, :index2, :index3, 1, :weight, :now)
+    ON CONFLICT(prev, curr, next) DO UPDATE SET
+        occurrences = occurrences + 1,
+        weight = weight + :weight,
+        last_seen = :now;
+    ";
+
+const NGRAM_INCREMENT_QUERY: &str = "
+INSERT INTO NGram (prefix, suffix, first, last, occurrences, weight, last_seen)
+    VALUES(:prefix, :suffix, :first, :last, 1, :weight, :now)
+    ON CONFLICT(prefix, last) DO UPDATE SET
+        occurrences = occurrences + 1,
+        weight = weight + :weight,
+        last_seen = :now;
+    ";
+
+// Only the total weight of an n-gram is stored, not the weight of each time
+// it was seen, so taking one occurrence away takes away the average. Removal is
+// exact for a model without a half-life and approximate for one with a half-life.
+const DECREMENT_QUERY: &str = "
+UPDATE Occurrence SET occurrences = occurrences - 1, weight = weight - weight / occurrences
+    WHERE prev = :index1 AND curr = :index2 AND next = :index3;
+DELETE FROM Occurrence
+    WHERE prev = :index1 AND curr = :index2 AND next = :index3 AND occurrences <= 0;
+    ";
+
+const NGRAM_DECREMENT_QUERY: &str = "
+UPDATE NGram SET occurrences = occurrences - 1, weight = weight - weight / occurrences
+    WHERE prefix = :prefix AND last = :last;
+DELETE FROM NGram WHERE prefix = :prefix AND last = :last AND occurrences <= 0;
+    ";
+
+const NGRAM_NEXT_QUERY: &str = "
+    SELECT last, weight FROM NGram WHERE prefix = :context;
+    ";
+
+const NGRAM_PREV_QUERY: &str = "
+    SELECT first, weight FROM NGram WHERE suffix = :context;
+    ";
+
+const INDEX_QUERY: &str = "
+    SELECT id FROM Words WHERE keyword = :keyword AND string = :string;
+    ";
+
+const FINGERPRINT_INCREMENT_QUERY: &str = "
+INSERT INTO Fingerprint (hash, occurrences, last_seen) VALUES(:hash, 1, :now)
+    ON CONFLICT(hash) DO UPDATE SET occurrences = occurrences + 1, last_seen = :now;
+    ";
+
+const FINGERPRINT_DECREMENT_QUERY: &str = "
+UPDATE Fingerprint SET occurrences = occurrences - 1 WHERE hash = :hash;
+DELETE FROM Fingerprint WHERE hash = :hash AND occurrences <= 0;
+    ";
+
+const LINE_INCREMENT_QUERY: &str = "
+INSERT INTO Line (hash, occurrences, last_seen) VALUES(:hash, 1, :now)
+    ON CONFLICT(hash) DO UPDATE SET occurrences = occurrences + 1, last_seen = :now;
+    ";
+
+const LINE_DECREMENT_QUERY: &str = "
+UPDATE Line SET occurrences = occurrences - 1 WHERE hash = :hash;
+DELETE FROM Line WHERE hash = :hash AND occurrences <= 0;
+    ";
+
+const REMOVE_ORPHAN_QUERY: &str = "
+DELETE FROM Words WHERE id = :id AND NOT EXISTS (
+    SELECT 1 FROM Occurrence WHERE curr = :id
+    );
+    ";
+
+const META_QUERY: &str = "
+    SELECT value FROM Meta WHERE key = :key;
+    ";
+
+const REBASE_QUERY: &str = "
+UPDATE Occurrence SET weight = weight * :factor;
+UPDATE NGram SET weight = weight * :factor;
+INSERT OR REPLACE INTO Meta (key, value) VALUES('decay_epoch', :now), ('half_life', :half_life);
+    ";
+
+const PRUNE_QUERY: &str = "
+DELETE FROM Occurrence WHERE weight < :threshold;
+DELETE FROM NGram WHERE weight < :threshold;
+DELETE FROM Fingerprint WHERE last_seen < :faded;
+    ";
+
+const PURGE_QUERY: &str = "
+DELETE FROM Occurrence WHERE last_seen < :cutoff;
+DELETE FROM NGram WHERE last_seen < :cutoff;
+DELETE FROM Fingerprint WHERE last_seen < :cutoff;
+DELETE FROM Line WHERE last_seen < :cutoff;
+INSERT OR REPLACE INTO Meta (key, value) VALUES('last_purge', :now);
+    ";
+
+const REMOVE_ORPHANS_QUERY: &str = "
+DELETE FROM Words WHERE keyword NOT IN ('start', 'end') AND NOT EXISTS (
+    SELECT 1 FROM Occurrence WHERE curr = Words.id
+    );
+    ";
+
+const CONTRIBUTION_ADD_QUERY: &str = "
+INSERT INTO Contribution (user_id, indices, timestamp) VALUES(:user_id, :indices, :timestamp);
+    ";
+
+const CONTRIBUTION_QUERY: &str = "
+    SELECT indices FROM Contribution WHERE user_id = :user_id;
+    ";
+
+const CONTRIBUTION_REMOVE_ONE_QUERY: &str = "
+DELETE FROM Contribution WHERE rowid = (
+    SELECT rowid FROM Contribution WHERE user_id = :user_id AND indices = :indices LIMIT 1
+    );
+    ";
+
+const CONTRIBUTION_REMOVE_QUERY: &str = "
+    DELETE FROM Contribution WHERE user_id = :user_id;
+    ";
+
+const CONTRIBUTION_OLDER_QUERY: &str = "
+    SELECT indices FROM Contribution WHERE timestamp < :cutoff;
+    ";
+
+const CONTRIBUTION_REMOVE_OLDER_QUERY: &str = "
+    DELETE FROM Contribution WHERE timestamp < :cutoff;
+    ";
+
+const FINGERPRINT_QUERY: &str = "
+    SELECT 1 FROM Fingerprint WHERE hash = :hash;
+    ";
+
+const LINE_QUERY: &str = "
+    SELECT 1 FROM Line WHERE hash = :hash;
     ";
 
 const GET_QUERY: &str = "
-    SELECT string FROM Words WHERE id = :id;
+    SELECT string, class FROM Words WHERE id = :id;
     ";
 
 const GET_CASE_INSENSITIVE: &str = "
@@ -42,23 +221,72 @@ const GET_CASE_INSENSITIVE: &str = "
     ";
 
 const SINGLE_NEXT_QUERY: &str = "
-    SELECT * FROM Occurrence WHERE curr = :index;
+    SELECT next, SUM(weight) AS weight FROM Occurrence WHERE curr = :index
+    GROUP BY next;
     ";
 
 const DOUBLE_NEXT_QUERY: &str = "
     SELECT * FROM Occurrence WHERE prev = :index1 AND curr = :index2;
     ";
 
-const DOUBLE_PREV_QUERY: &str = "
-    SELECT * FROM Occurrence WHERE curr = :index1 AND next = :index2;
+const SINGLE_PREV_QUERY: &str = "
+    SELECT prev, SUM(weight) AS weight FROM Occurrence WHERE curr = :index
+    GROUP BY prev;
     ";
 
-const REVERSE_QUERY: &str = "
-    SELECT occurrences FROM Occurrence WHERE curr = :index1 and next = :index2;
+const DOUBLE_PREV_QUERY: &str = "
+    SELECT prev, SUM(weight) AS weight FROM Occurrence
+    WHERE curr = :index1 AND next = :index2
+    GROUP BY prev;
     ";
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 
+fn join_indices(indices: &[u64]) -> String {
+    indices.iter().join(" ")
+}
+
+fn split_indices(indices: &str) -> Result<Vec<u64>, Error> {
+    Ok(indices
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()?)
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64)
+}
+
+/// Unix time `max_age` seconds ago.
+fn cutoff(max_age: u64) -> Result<i64, Error> {
+    i64::try_from(max_age)
+        .ok()
+        .and_then(|max_age| now().checked_sub(max_age))
+        .ok_or_else(|| format!("{} seconds is too long a period", max_age).into())
+}
+
+/// How learned counts fade. Stored weights are counts scaled by
+/// 2^((seen - epoch) / half_life), so a whole query decays by one factor.
+#[derive(Clone, Copy)]
+struct Decay {
+    epoch: i64,
+    /// Seconds, 0 means counts never fade.
+    half_life: i64,
+}
+
+impl Decay {
+    /// The stored weight of one occurrence seen at `time`.
+    fn scale(&self, time: i64) -> f64 {
+        if self.half_life <= 0 {
+            1.0
+        } else {
+            ((time - self.epoch) as f64 / self.half_life as f64).exp2()
+        }
+    }
+}
+
 pub struct SqliteDB {
     connection: sqlite::ConnectionWithFullMutex,
 }
@@ -68,9 +296,172 @@ impl SqliteDB {
         let db = SqliteDB {
             connection: sqlite::Connection::open_with_full_mutex(path)?,
         };
+        // Older versions kept every attributed line as text. It is moved aside
+        // until `legacy_contributions` has turned it into word ids
+        if db.has_column("Contribution", "line")? {
+            db.connection.execute(
+                "ALTER TABLE Contribution RENAME TO LegacyContribution;
+                DROP INDEX IF EXISTS ContributionUser;",
+            )?;
+        }
         db.connection.execute(INIT_QUERY)?;
+        // Whole lines are only fingerprinted in models that were empty when
+        // that started
+        db.connection.execute(
+            "INSERT OR IGNORE INTO Meta (key, value) SELECT 'line_hashes',
+                NOT EXISTS (SELECT 1 FROM Occurrence) AND NOT EXISTS (SELECT 1 FROM NGram);",
+        )?;
+        for table in ["Occurrence", "NGram"] {
+            db.migrate_column(
+                table,
+                "weight",
+                "REAL NOT NULL DEFAULT 0",
+                "weight = occurrences",
+            )?;
+        }
+        // Rows from before last_seen was tracked count as seen when they were
+        // migrated, so a retention period starts counting from the upgrade
+        for table in ["Occurrence", "NGram", "Fingerprint"] {
+            db.migrate_column(
+                table,
+                "last_seen",
+                "INT NOT NULL DEFAULT 0",
+                "last_seen = CAST(strftime('%s', 'now') AS INT)",
+            )?;
+        }
+        // A close enough guess at the tags the tokenizer would have given
+        db.migrate_column(
+            "Words",
+            "class",
+            "TEXT",
+            "class = CASE
+                WHEN string LIKE '%://%' OR string LIKE 'www.%' OR string LIKE 't.me/%' THEN 'url'
+                WHEN string GLOB '@?*' AND string NOT GLOB '@*[^A-Za-z0-9_]*' THEN 'mention'
+                WHEN string GLOB '#?*' AND string NOT GLOB '#*[^A-Za-z0-9_]*' THEN 'hashtag'
+                WHEN string GLOB '/?*' AND string NOT GLOB '/*[^A-Za-z0-9_@]*' THEN 'command'
+                WHEN string GLOB '[0-9]*' AND string NOT GLOB '*[^0-9.,]*' THEN 'number'
+            END",
+        )?;
         Ok(db)
     }
+
+    fn has_column(&self, table: &str, column: &str) -> Result<bool, Error> {
+        let mut statement = self
+            .connection
+            .prepare(format!("PRAGMA table_info({})", table))?;
+        while let Ok(sqlite::State::Row) = statement.next() {
+            if statement.read::<String, _>("name")? == column {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Adds a column that models made by older versions lack, filling it in
+    /// for the rows they already have.
+    fn migrate_column(
+        &self,
+        table: &str,
+        column: &str,
+        definition: &str,
+        fill: &str,
+    ) -> Result<(), Error> {
+        if self.has_column(table, column)? {
+            return Ok(());
+        }
+
+        self.connection.execute(format!(
+            "ALTER TABLE {table} ADD COLUMN {column} {definition}; UPDATE {table} SET {fill};"
+        ))?;
+        Ok(())
+    }
+
+    fn meta(&self, key: &str) -> Result<Option<i64>, Error> {
+        let mut statement = self.connection.prepare(META_QUERY)?;
+        statement.bind((":key", key))?;
+
+        if let Ok(sqlite::State::Row) = statement.next() {
+            Ok(Some(statement.read::<i64, _>("value")?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn decay(&self) -> Result<Decay, Error> {
+        Ok(Decay {
+            epoch: self.meta("decay_epoch")?.unwrap_or(0),
+            half_life: self.meta("half_life")?.unwrap_or(0),
+        })
+    }
+
+    /// Folds the decay so far into the stored weights and moves the epoch to
+    /// now, switching to `half_life` from here on. The epoch and the weights
+    /// must move together, so callers run this inside a `transaction`.
+    fn rebase(&self, half_life: i64) -> Result<(), Error> {
+        let now = now();
+        let factor = 1.0 / self.decay()?.scale(now);
+
+        self.execute_each(
+            REBASE_QUERY,
+            &[
+                (":factor", factor.into()),
+                (":now", now.into()),
+                (":half_life", half_life.into()),
+            ],
+        )
+    }
+
+    /// Runs `f` as one transaction, so a statement failing partway through
+    /// (say with `SQLITE_BUSY` while another connection writes) leaves none
+    /// of the others applied.
+    fn transaction<T>(&self, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        self.connection.execute("BEGIN;")?;
+        let result = f().and_then(|value| {
+            self.connection.execute("COMMIT;")?;
+            Ok(value)
+        });
+        if result.is_err() {
+            // A failed COMMIT leaves the transaction open, so it is undone too
+            let _ = self.connection.execute("ROLLBACK;");
+        }
+        result
+    }
+
+    /// Reads `(index, weight)` rows and decays the weights to the present.
+    fn read_weights(
+        &self,
+        statement: &mut sqlite::Statement,
+        column: &str,
+    ) -> Result<Vec<(u64, f64)>, Error> {
+        let scale = self.decay()?.scale(now());
+
+        let mut vec: Vec<(u64, f64)> = vec![];
+        while let Ok(sqlite::State::Row) = statement.next() {
+            vec.push((
+                statement.read::<i64, _>(column)? as u64,
+                statement.read::<f64, _>("weight")? / scale,
+            ));
+        }
+        Ok(vec)
+    }
+
+    /// Runs every statement in `queries`, binding whichever of `bindings` each
+    /// one uses. `Connection::execute` can't bind anything.
+    fn execute_each(&self, queries: &str, bindings: &[(&str, sqlite::Value)]) -> Result<(), Error> {
+        for query in queries
+            .split_inclusive(';')
+            .filter(|query| !query.trim().is_empty())
+        {
+            let mut statement = self.connection.prepare(query)?;
+            for (name, value) in bindings {
+                if statement.parameter_index(name)?.is_some() {
+                    statement.bind((*name, value.clone()))?;
+                }
+            }
+            while statement.next()? == sqlite::State::Row {}
+        }
+        Ok(())
+    }
 }
 
 pub struct SqliteBlacklist {
@@ -90,6 +481,19 @@ impl SqliteBlacklist {
                 user_id INT NOT NULL,
                 UNIQUE(chat_id, user_id)
                 );
+
+            CREATE TABLE IF NOT EXISTS OptOut(
+                chat_id INT NOT NULL,
+                user_id INT NOT NULL,
+                UNIQUE(chat_id, user_id)
+                );
+
+            CREATE TABLE IF NOT EXISTS Members(
+                chat_id INT NOT NULL,
+                user_id INT NOT NULL,
+                username TEXT,
+                UNIQUE(chat_id, user_id)
+                );
             ",
         )?;
         Ok(db)
@@ -100,27 +504,120 @@ impl SqliteBlacklist {
 pub trait Database {
     async fn add_word(&self, tuple: (&str, &str)) -> Result<u64, Error>;
 
+    /// Adds a word tagged with its `TokenClass`. A word that already has a tag
+    /// keeps it when added again without one.
+    async fn add_classified_word(
+        &self,
+        tuple: (&str, &str),
+        class: Option<&str>,
+    ) -> Result<u64, Error>;
+
     async fn increment(&self, index1: u64, index2: u64, index3: u64) -> Result<(), Error>;
 
+    /// Counts an n-gram of any length, the last index being the word that follows
+    /// the rest. Used for contexts longer than the `Occurrence` triples can hold.
+    async fn increment_ngram(&self, indices: &[u64]) -> Result<(), Error>;
+
+    /// Takes one away from a triple's count and deletes it once nothing is left.
+    /// The weight goes down by the triple's average weight.
+    async fn decrement(&self, index1: u64, index2: u64, index3: u64) -> Result<(), Error>;
+
+    async fn decrement_ngram(&self, indices: &[u64]) -> Result<(), Error>;
+
+    /// Records a hashed run of words from a learned line.
+    async fn increment_fingerprint(&self, hash: u64) -> Result<(), Error>;
+
+    async fn decrement_fingerprint(&self, hash: u64) -> Result<(), Error>;
+
+    /// Records the hash of a whole learned line. Unlike the fingerprints of
+    /// runs, these only go when their line is forgotten or purged, never as
+    /// they fade, because the line's n-grams can outlive it in other lines.
+    async fn increment_line(&self, hash: u64) -> Result<(), Error>;
+
+    async fn decrement_line(&self, hash: u64) -> Result<(), Error>;
+
+    /// Deletes a word once no learned line uses it any more.
+    async fn remove_orphan(&self, index: u64) -> Result<(), Error>;
+
+    /// Records that `user_id` wrote the line made of the words `indices`, so
+    /// it can be taken back later. `timestamp` defaults to now.
+    async fn add_contribution(
+        &self,
+        user_id: u64,
+        indices: &[u64],
+        timestamp: Option<u64>,
+    ) -> Result<(), Error>;
+
+    async fn get_contributions(&self, user_id: u64) -> Result<Vec<Vec<u64>>, Error>;
+
+    async fn remove_contributions(&self, user_id: u64) -> Result<(), Error>;
+
+    /// Forgets one record of `user_id` writing the line made of `indices`.
+    async fn remove_contribution(&self, user_id: u64, indices: &[u64]) -> Result<(), Error>;
+
+    /// Makes counts fade by half every `half_life` seconds from now on. 0 stops
+    /// the fading.
+    async fn set_half_life(&self, half_life: u64) -> Result<(), Error>;
+
+    /// Shrinks the stored weights by the decay so far and prunes every n-gram
+    /// whose weight fell below `threshold`, along with words nothing uses any more
+    /// and fingerprints too old to belong to anything that's left.
+    async fn maintain(&self, threshold: f64) -> Result<(), Error>;
+
+    /// Lines contributed more than `max_age` seconds ago.
+    async fn get_contributions_older(&self, max_age: u64) -> Result<Vec<Vec<u64>>, Error>;
+
+    /// Attributed lines an older version stored as text, with their user and
+    /// timestamp.
+    async fn legacy_contributions(&self) -> Result<Vec<(u64, String, u64)>, Error>;
+
+    async fn remove_legacy_contributions(&self) -> Result<(), Error>;
+
+    /// Deletes contributions, n-grams, fingerprints and line hashes not seen
+    /// for `max_age` seconds, then shrinks the file.
+    async fn purge(&self, max_age: u64) -> Result<(), Error>;
+
+    /// Unix time of the last purge.
+    async fn last_purge(&self) -> Result<Option<u64>, Error>;
+
+    async fn has_fingerprint(&self, hash: u64) -> Result<bool, Error>;
+
+    async fn has_line(&self, hash: u64) -> Result<bool, Error>;
+
+    /// Whether the hash of every whole line learned was kept. Models that
+    /// already had lines before that was done don't have them.
+    async fn tracks_lines(&self) -> Result<bool, Error>;
+
     async fn get_word(&self, index: u64) -> Result<String, Error>;
 
+    /// A word together with its class tag.
+    async fn get_classified_word(&self, index: u64) -> Result<(String, Option<String>), Error>;
+
+    /// Looks up a word's id without adding it.
+    async fn get_index(&self, tuple: (&str, &str)) -> Result<Option<u64>, Error>;
+
     async fn get_case_insensitive(&self, string: &str) -> Result<Vec<(u64, String)>, Error>;
 
-    async fn get_single_occurrences(&self, index: u64) -> Result<Vec<(u64, u64)>, Error>;
+    /// The getters below return weights: counts decayed by their age.
+    async fn get_single_occurrences(&self, index: u64) -> Result<Vec<(u64, f64)>, Error>;
 
     async fn get_double_occurrences(
         &self,
         index1: u64,
         index2: u64,
-    ) -> Result<Vec<(u64, u64)>, Error>;
+    ) -> Result<Vec<(u64, f64)>, Error>;
 
-    async fn get_prev_single_occurrences(&self, index: u64) -> Result<Vec<(u64, u64)>, Error>;
+    async fn get_prev_single_occurrences(&self, index: u64) -> Result<Vec<(u64, f64)>, Error>;
 
     async fn get_prev_double_occurrences(
         &self,
         index1: u64,
         index2: u64,
-    ) -> Result<Vec<(u64, u64)>, Error>;
+    ) -> Result<Vec<(u64, f64)>, Error>;
+
+    async fn get_ngram_occurrences(&self, context: &[u64]) -> Result<Vec<(u64, f64)>, Error>;
+
+    async fn get_prev_ngram_occurrences(&self, context: &[u64]) -> Result<Vec<(u64, f64)>, Error>;
 }
 
 #[async_trait]
@@ -132,14 +629,50 @@ pub trait Blacklist {
     async fn is_blacklisted(&self, chat_id: i64, user_id: u64) -> Result<bool, Error>;
 }
 
+/// Users who asked not to be learned from.
+#[async_trait]
+pub trait OptOut {
+    async fn opt_out(&self, chat_id: i64, user_id: u64) -> Result<(), Error>;
+
+    async fn opt_in(&self, chat_id: i64, user_id: u64) -> Result<(), Error>;
+
+    async fn is_opted_out(&self, chat_id: i64, user_id: u64) -> Result<bool, Error>;
+}
+
+/// Users the bot has learned from, so they can be found by their username.
+#[async_trait]
+pub trait Members {
+    async fn remember(
+        &self,
+        chat_id: i64,
+        user_id: u64,
+        username: Option<&str>,
+    ) -> Result<(), Error>;
+
+    async fn find_username(&self, chat_id: i64, username: &str) -> Result<Option<u64>, Error>;
+
+    /// Every member of a chat whose username is known, leaving out blacklisted
+    /// and opted out users.
+    async fn get_members(&self, chat_id: i64) -> Result<Vec<(u64, String)>, Error>;
+}
+
 #[async_trait]
 impl Database for SqliteDB {
     async fn add_word(&self, tuple: (&str, &str)) -> Result<u64, Error> {
+        self.add_classified_word(tuple, None).await
+    }
+
+    async fn add_classified_word(
+        &self,
+        tuple: (&str, &str),
+        class: Option<&str>,
+    ) -> Result<u64, Error> {
         let mut statement = self.connection.prepare(ADD_QUERY)?;
 
         statement.bind_iter::<_, (_, sqlite::Value)>([
-            (":keyword", tuple.0.clone().into()),
-            (":string", tuple.1.clone().into()),
+            (":keyword", tuple.0.into()),
+            (":string", tuple.1.into()),
+            (":class", class.map_or(sqlite::Value::Null, Into::into)),
         ])?;
 
         while let Ok(sqlite::State::Row) = statement.next() {}
@@ -161,22 +694,281 @@ impl Database for SqliteDB {
     }
 
     async fn increment(&self, index1: u64, index2: u64, index3: u64) -> Result<(), Error> {
+        let now = now();
         let mut statement = self.connection.prepare(INCREMENT_QUERY)?;
-        statement.bind_iter::<_, (_, i64)>([
-            (":index1", index1 as i64),
-            (":index2", index2 as i64),
-            (":index3", index3 as i64),
+        statement.bind_iter::<_, (_, sqlite::Value)>([
+            (":index1", (index1 as i64).into()),
+            (":index2", (index2 as i64).into()),
+            (":index3", (index3 as i64).into()),
+            (":weight", self.decay()?.scale(now).into()),
+            (":now", now.into()),
         ])?;
         while let Ok(sqlite::State::Row) = statement.next() {}
         Ok(())
     }
 
+    async fn increment_ngram(&self, indices: &[u64]) -> Result<(), Error> {
+        if indices.len() < 2 {
+            let err: Error = String::from("An n-gram needs at least two words").into();
+            return Err(err);
+        }
+
+        let now = now();
+        let mut statement = self.connection.prepare(NGRAM_INCREMENT_QUERY)?;
+        statement.bind_iter::<_, (_, sqlite::Value)>([
+            (
+                ":prefix",
+                join_indices(&indices[..indices.len() - 1]).into(),
+            ),
+            (":suffix", join_indices(&indices[1..]).into()),
+            (":first", (indices[0] as i64).into()),
+            (":last", (indices[indices.len() - 1] as i64).into()),
+            (":weight", self.decay()?.scale(now).into()),
+            (":now", now.into()),
+        ])?;
+        while let Ok(sqlite::State::Row) = statement.next() {}
+        Ok(())
+    }
+
+    async fn decrement(&self, index1: u64, index2: u64, index3: u64) -> Result<(), Error> {
+        self.transaction(|| {
+            self.execute_each(
+                DECREMENT_QUERY,
+                &[
+                    (":index1", (index1 as i64).into()),
+                    (":index2", (index2 as i64).into()),
+                    (":index3", (index3 as i64).into()),
+                ],
+            )
+        })
+    }
+
+    async fn decrement_ngram(&self, indices: &[u64]) -> Result<(), Error> {
+        if indices.len() < 2 {
+            let err: Error = String::from("An n-gram needs at least two words").into();
+            return Err(err);
+        }
+
+        self.transaction(|| {
+            self.execute_each(
+                NGRAM_DECREMENT_QUERY,
+                &[
+                    (
+                        ":prefix",
+                        join_indices(&indices[..indices.len() - 1]).into(),
+                    ),
+                    (":last", (indices[indices.len() - 1] as i64).into()),
+                ],
+            )
+        })
+    }
+
+    async fn increment_fingerprint(&self, hash: u64) -> Result<(), Error> {
+        let mut statement = self.connection.prepare(FINGERPRINT_INCREMENT_QUERY)?;
+        statement.bind_iter::<_, (_, sqlite::Value)>([
+            (":hash", (hash as i64).into()),
+            (":now", now().into()),
+        ])?;
+        while statement.next()? == sqlite::State::Row {}
+        Ok(())
+    }
+
+    async fn decrement_fingerprint(&self, hash: u64) -> Result<(), Error> {
+        self.transaction(|| {
+            self.execute_each(
+                FINGERPRINT_DECREMENT_QUERY,
+                &[(":hash", (hash as i64).into())],
+            )
+        })
+    }
+
+    async fn increment_line(&self, hash: u64) -> Result<(), Error> {
+        let mut statement = self.connection.prepare(LINE_INCREMENT_QUERY)?;
+        statement.bind_iter::<_, (_, sqlite::Value)>([
+            (":hash", (hash as i64).into()),
+            (":now", now().into()),
+        ])?;
+        while statement.next()? == sqlite::State::Row {}
+        Ok(())
+    }
+
+    async fn decrement_line(&self, hash: u64) -> Result<(), Error> {
+        self.transaction(|| {
+            self.execute_each(LINE_DECREMENT_QUERY, &[(":hash", (hash as i64).into())])
+        })
+    }
+
+    async fn remove_orphan(&self, index: u64) -> Result<(), Error> {
+        let mut statement = self.connection.prepare(REMOVE_ORPHAN_QUERY)?;
+        statement.bind((":id", index as i64))?;
+        while let Ok(sqlite::State::Row) = statement.next() {}
+        Ok(())
+    }
+
+    async fn add_contribution(
+        &self,
+        user_id: u64,
+        indices: &[u64],
+        timestamp: Option<u64>,
+    ) -> Result<(), Error> {
+        let mut statement = self.connection.prepare(CONTRIBUTION_ADD_QUERY)?;
+        statement.bind_iter::<_, (_, sqlite::Value)>([
+            (":user_id", (user_id as i64).into()),
+            (":indices", join_indices(indices).into()),
+            (
+                ":timestamp",
+                timestamp.map_or_else(now, |time| time as i64).into(),
+            ),
+        ])?;
+        while let Ok(sqlite::State::Row) = statement.next() {}
+        Ok(())
+    }
+
+    async fn get_contributions(&self, user_id: u64) -> Result<Vec<Vec<u64>>, Error> {
+        let mut statement = self.connection.prepare(CONTRIBUTION_QUERY)?;
+        statement.bind((":user_id", user_id as i64))?;
+
+        let mut vec = vec![];
+        while let Ok(sqlite::State::Row) = statement.next() {
+            vec.push(split_indices(&statement.read::<String, _>("indices")?)?);
+        }
+        Ok(vec)
+    }
+
+    async fn remove_contributions(&self, user_id: u64) -> Result<(), Error> {
+        let mut statement = self.connection.prepare(CONTRIBUTION_REMOVE_QUERY)?;
+        statement.bind((":user_id", user_id as i64))?;
+        while let Ok(sqlite::State::Row) = statement.next() {}
+        Ok(())
+    }
+
+    async fn remove_contribution(&self, user_id: u64, indices: &[u64]) -> Result<(), Error> {
+        let mut statement = self.connection.prepare(CONTRIBUTION_REMOVE_ONE_QUERY)?;
+        statement.bind_iter::<_, (_, sqlite::Value)>([
+            (":user_id", (user_id as i64).into()),
+            (":indices", join_indices(indices).into()),
+        ])?;
+        while let Ok(sqlite::State::Row) = statement.next() {}
+        Ok(())
+    }
+
+    async fn set_half_life(&self, half_life: u64) -> Result<(), Error> {
+        let half_life = i64::try_from(half_life)?;
+        if self.decay()?.half_life == half_life {
+            return Ok(());
+        }
+        self.transaction(|| self.rebase(half_life))
+    }
+
+    async fn maintain(&self, threshold: f64) -> Result<(), Error> {
+        let decay = self.decay()?;
+        if decay.half_life == 0 {
+            return Ok(());
+        }
+
+        // A run of words last seen this long ago has decayed below the
+        // threshold even if it was learned once, so its fingerprint goes too
+        let fade = decay.half_life as f64 * threshold.recip().log2();
+        let faded = now().saturating_sub(fade as i64);
+
+        self.transaction(|| {
+            self.rebase(decay.half_life)?;
+            self.execute_each(
+                PRUNE_QUERY,
+                &[(":threshold", threshold.into()), (":faded", faded.into())],
+            )?;
+            self.execute_each(REMOVE_ORPHANS_QUERY, &[])
+        })
+    }
+
+    async fn get_contributions_older(&self, max_age: u64) -> Result<Vec<Vec<u64>>, Error> {
+        let mut statement = self.connection.prepare(CONTRIBUTION_OLDER_QUERY)?;
+        statement.bind((":cutoff", cutoff(max_age)?))?;
+
+        let mut vec = vec![];
+        while let Ok(sqlite::State::Row) = statement.next() {
+            vec.push(split_indices(&statement.read::<String, _>("indices")?)?);
+        }
+        Ok(vec)
+    }
+
+    async fn legacy_contributions(&self) -> Result<Vec<(u64, String, u64)>, Error> {
+        if !self.has_column("LegacyContribution", "line")? {
+            return Ok(vec![]);
+        }
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT user_id, line, timestamp FROM LegacyContribution;")?;
+        let mut vec = vec![];
+        while let Ok(sqlite::State::Row) = statement.next() {
+            vec.push((
+                statement.read::<i64, _>("user_id")? as u64,
+                statement.read::<String, _>("line")?,
+                statement.read::<i64, _>("timestamp")? as u64,
+            ));
+        }
+        Ok(vec)
+    }
+
+    async fn remove_legacy_contributions(&self) -> Result<(), Error> {
+        self.connection
+            .execute("DROP TABLE IF EXISTS LegacyContribution;")?;
+        Ok(())
+    }
+
+    async fn purge(&self, max_age: u64) -> Result<(), Error> {
+        let now = now();
+        let cutoff = cutoff(max_age)?;
+
+        self.transaction(|| {
+            self.execute_each(
+                CONTRIBUTION_REMOVE_OLDER_QUERY,
+                &[(":cutoff", cutoff.into())],
+            )?;
+            self.execute_each(
+                PURGE_QUERY,
+                &[(":cutoff", cutoff.into()), (":now", now.into())],
+            )?;
+            self.execute_each(REMOVE_ORPHANS_QUERY, &[])
+        })?;
+        self.connection.execute("VACUUM;")?;
+        Ok(())
+    }
+
+    async fn last_purge(&self) -> Result<Option<u64>, Error> {
+        Ok(self.meta("last_purge")?.map(|time| time as u64))
+    }
+
+    async fn has_fingerprint(&self, hash: u64) -> Result<bool, Error> {
+        let mut statement = self.connection.prepare(FINGERPRINT_QUERY)?;
+        statement.bind((":hash", hash as i64))?;
+        Ok(matches!(statement.next(), Ok(sqlite::State::Row)))
+    }
+
+    async fn has_line(&self, hash: u64) -> Result<bool, Error> {
+        let mut statement = self.connection.prepare(LINE_QUERY)?;
+        statement.bind((":hash", hash as i64))?;
+        Ok(statement.next()? == sqlite::State::Row)
+    }
+
+    async fn tracks_lines(&self) -> Result<bool, Error> {
+        Ok(self.meta("line_hashes")? == Some(1))
+    }
+
     async fn get_word(&self, index: u64) -> Result<String, Error> {
+        Ok(self.get_classified_word(index).await?.0)
+    }
+
+    async fn get_classified_word(&self, index: u64) -> Result<(String, Option<String>), Error> {
         let mut statement = self.connection.prepare(GET_QUERY)?;
         statement.bind((":id", index as i64))?;
 
         if let Ok(sqlite::State::Row) = statement.next() {
-            Ok(statement.read::<String, _>("string")?)
+            Ok((
+                statement.read::<String, _>("string")?,
+                statement.read::<Option<String>, _>("class")?,
+            ))
         } else {
             let err: Error =
                 String::from("None was returned. Is your file corrupted or missing?").into();
@@ -184,6 +976,20 @@ impl Database for SqliteDB {
         }
     }
 
+    async fn get_index(&self, tuple: (&str, &str)) -> Result<Option<u64>, Error> {
+        let mut statement = self.connection.prepare(INDEX_QUERY)?;
+        statement.bind_iter::<_, (_, sqlite::Value)>([
+            (":keyword", tuple.0.into()),
+            (":string", tuple.1.into()),
+        ])?;
+
+        if let Ok(sqlite::State::Row) = statement.next() {
+            Ok(Some(statement.read::<i64, _>("id")? as u64))
+        } else {
+            Ok(None)
+        }
+    }
+
     async fn get_case_insensitive(&self, string: &str) -> Result<Vec<(u64, String)>, Error> {
         let mut statement = self.connection.prepare(GET_CASE_INSENSITIVE)?;
         statement.bind((":string", string.to_lowercase().as_str()))?;
@@ -198,98 +1004,56 @@ impl Database for SqliteDB {
         Ok(vec)
     }
 
-    async fn get_single_occurrences(&self, index: u64) -> Result<Vec<(u64, u64)>, Error> {
+    async fn get_single_occurrences(&self, index: u64) -> Result<Vec<(u64, f64)>, Error> {
         let mut statement = self.connection.prepare(SINGLE_NEXT_QUERY)?;
         statement.bind((":index", index as i64))?;
 
-        let mut vec: Vec<(u64, u64)> = vec![];
-        while let Ok(sqlite::State::Row) = statement.next() {
-            vec.push((
-                statement.read::<i64, _>("next")? as u64,
-                statement.read::<i64, _>("occurrences")? as u64,
-            ));
-        }
-        Ok(vec)
+        self.read_weights(&mut statement, "next")
     }
 
     async fn get_double_occurrences(
         &self,
         index1: u64,
         index2: u64,
-    ) -> Result<Vec<(u64, u64)>, Error> {
+    ) -> Result<Vec<(u64, f64)>, Error> {
         let mut statement = self.connection.prepare(DOUBLE_NEXT_QUERY)?;
         statement
             .bind_iter::<_, (_, i64)>([(":index1", index1 as i64), (":index2", index2 as i64)])?;
 
-        let mut vec: Vec<(u64, u64)> = vec![];
-        while let Ok(sqlite::State::Row) = statement.next() {
-            vec.push((
-                statement.read::<i64, _>("next").unwrap() as u64,
-                statement.read::<i64, _>("occurrences").unwrap() as u64,
-            ));
-        }
-        Ok(vec)
+        self.read_weights(&mut statement, "next")
     }
 
-    async fn get_prev_single_occurrences(&self, index: u64) -> Result<Vec<(u64, u64)>, Error> {
-        let mut statement = self.connection.prepare(SINGLE_NEXT_QUERY)?;
+    async fn get_prev_single_occurrences(&self, index: u64) -> Result<Vec<(u64, f64)>, Error> {
+        let mut statement = self.connection.prepare(SINGLE_PREV_QUERY)?;
         statement.bind((":index", index as i64))?;
 
-        let mut vec1: Vec<u64> = vec![];
-        let mut vec2: Vec<u64> = vec![];
-        while let Ok(sqlite::State::Row) = statement.next() {
-            vec1.push(statement.read::<i64, _>("prev").unwrap() as u64);
-        }
-
-        if vec1.is_empty() {
-            let err: Error = String::from("The length of vec is 0").into();
-            return Err(err);
-        }
-        let prev = vec1[0];
-
-        let mut statement = self.connection.prepare(REVERSE_QUERY)?;
-        statement
-            .bind_iter::<_, (_, i64)>([(":index1", prev as i64), (":index2", index as i64)])?;
-        while let Ok(sqlite::State::Row) = statement.next() {
-            vec2.push(statement.read::<i64, _>("occurrences").unwrap() as u64);
-        }
-
-        let vec = vec1.into_iter().zip(vec2).collect();
-
-        Ok(vec)
+        self.read_weights(&mut statement, "prev")
     }
 
     async fn get_prev_double_occurrences(
         &self,
         index1: u64,
         index2: u64,
-    ) -> Result<Vec<(u64, u64)>, Error> {
+    ) -> Result<Vec<(u64, f64)>, Error> {
         let mut statement = self.connection.prepare(DOUBLE_PREV_QUERY)?;
         statement
             .bind_iter::<_, (_, i64)>([(":index1", index1 as i64), (":index2", index2 as i64)])?;
 
-        let mut vec1: Vec<u64> = vec![];
-        let mut vec2: Vec<u64> = vec![];
-        while let Ok(sqlite::State::Row) = statement.next() {
-            vec1.push(statement.read::<i64, _>("prev").unwrap() as u64);
-        }
+        self.read_weights(&mut statement, "prev")
+    }
 
-        if vec1.is_empty() {
-            let err: Error = String::from("The length of vec is 0").into();
-            return Err(err);
-        }
-        let prev = vec1[0];
+    async fn get_ngram_occurrences(&self, context: &[u64]) -> Result<Vec<(u64, f64)>, Error> {
+        let mut statement = self.connection.prepare(NGRAM_NEXT_QUERY)?;
+        statement.bind((":context", join_indices(context).as_str()))?;
 
-        let mut statement = self.connection.prepare(REVERSE_QUERY)?;
-        statement
-            .bind_iter::<_, (_, i64)>([(":index1", prev as i64), (":index2", index1 as i64)])?;
-        while let Ok(sqlite::State::Row) = statement.next() {
-            vec2.push(statement.read::<i64, _>("occurrences").unwrap() as u64);
-        }
+        self.read_weights(&mut statement, "last")
+    }
 
-        let vec = vec1.into_iter().zip(vec2).collect();
+    async fn get_prev_ngram_occurrences(&self, context: &[u64]) -> Result<Vec<(u64, f64)>, Error> {
+        let mut statement = self.connection.prepare(NGRAM_PREV_QUERY)?;
+        statement.bind((":context", join_indices(context).as_str()))?;
 
-        Ok(vec)
+        self.read_weights(&mut statement, "first")
     }
 }
 
@@ -329,10 +1093,119 @@ impl Blacklist for SqliteBlacklist {
         let mut is_blacklisted = false;
 
         while let Ok(sqlite::State::Row) = statement.next() {
-            if let Ok(_) = statement.read::<i64, _>("1") {
+            if statement.read::<i64, _>("1").is_ok() {
                 is_blacklisted = true;
             }
         }
         Ok(is_blacklisted)
     }
 }
+
+#[async_trait]
+impl Members for SqliteBlacklist {
+    async fn remember(
+        &self,
+        chat_id: i64,
+        user_id: u64,
+        username: Option<&str>,
+    ) -> Result<(), Error> {
+        let mut statement = self.connection.prepare(
+            "INSERT INTO Members (chat_id, user_id, username) VALUES(:chat_id, :user_id, :username)
+                ON CONFLICT(chat_id, user_id) DO UPDATE SET username = :username;",
+        )?;
+
+        statement.bind_iter::<_, (_, sqlite::Value)>([
+            (":chat_id", chat_id.into()),
+            (":user_id", (user_id as i64).into()),
+            (
+                ":username",
+                username.map_or(sqlite::Value::Null, |username| username.into()),
+            ),
+        ])?;
+
+        while let Ok(sqlite::State::Row) = statement.next() {}
+        Ok(())
+    }
+
+    async fn find_username(&self, chat_id: i64, username: &str) -> Result<Option<u64>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT user_id FROM Members WHERE chat_id = :chat_id AND LOWER(username) = :username;",
+        )?;
+
+        statement.bind_iter::<_, (_, sqlite::Value)>([
+            (":chat_id", chat_id.into()),
+            (":username", username.to_lowercase().into()),
+        ])?;
+
+        if let Ok(sqlite::State::Row) = statement.next() {
+            Ok(Some(statement.read::<i64, _>("user_id")? as u64))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn get_members(&self, chat_id: i64) -> Result<Vec<(u64, String)>, Error> {
+        let mut statement = self.connection.prepare(
+            "
+            SELECT user_id, username FROM Members
+                WHERE chat_id = :chat_id AND username IS NOT NULL
+                AND NOT EXISTS (
+                    SELECT 1 FROM Blacklist
+                    WHERE chat_id = Members.chat_id AND user_id = Members.user_id
+                    )
+                AND NOT EXISTS (
+                    SELECT 1 FROM OptOut
+                    WHERE chat_id = Members.chat_id AND user_id = Members.user_id
+                    );
+            ",
+        )?;
+        statement.bind((":chat_id", chat_id))?;
+
+        let mut vec = vec![];
+        while let Ok(sqlite::State::Row) = statement.next() {
+            vec.push((
+                statement.read::<i64, _>("user_id")? as u64,
+                statement.read::<String, _>("username")?,
+            ));
+        }
+        Ok(vec)
+    }
+}
+
+#[async_trait]
+impl OptOut for SqliteBlacklist {
+    async fn opt_out(&self, chat_id: i64, user_id: u64) -> Result<(), Error> {
+        let mut statement = self.connection.prepare(
+            "INSERT OR IGNORE INTO OptOut (chat_id, user_id) VALUES(:chat_id, :user_id);",
+        )?;
+
+        statement
+            .bind_iter::<_, (_, i64)>([(":chat_id", chat_id), (":user_id", user_id as i64)])?;
+
+        while let Ok(sqlite::State::Row) = statement.next() {}
+        Ok(())
+    }
+
+    async fn opt_in(&self, chat_id: i64, user_id: u64) -> Result<(), Error> {
+        let mut statement = self
+            .connection
+            .prepare("DELETE FROM OptOut WHERE chat_id = :chat_id AND user_id = :user_id;")?;
+
+        statement
+            .bind_iter::<_, (_, i64)>([(":chat_id", chat_id), (":user_id", user_id as i64)])?;
+
+        while let Ok(sqlite::State::Row) = statement.next() {}
+        Ok(())
+    }
+
+    async fn is_opted_out(&self, chat_id: i64, user_id: u64) -> Result<bool, Error> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT 1 FROM OptOut WHERE chat_id = :chat_id AND user_id = :user_id;")?;
+
+        statement
+            .bind_iter::<_, (_, i64)>([(":chat_id", chat_id), (":user_id", user_id as i64)])?;
+
+        Ok(matches!(statement.next(), Ok(sqlite::State::Row)))
+    }
+}
@@ -4,13 +4,39 @@ use std::env;
 use std::sync::Arc;
 
 use sneedov::database::SqliteDB;
-use sneedov::markov::sneedov_feed;
-use sneedov::telegram::start_dispatcher;
+use sneedov::markov::{sneedov_feed, Markov};
+use sneedov::telegram::config::get_config;
+use sneedov::telegram::{configure, start_dispatcher};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     {
         let args: Vec<String> = env::args().collect();
+        if args.len() > 2 && args[1] == "--generate" {
+            let path_name = format!("./{d}/model.db", d = &args[2]);
+            let path = std::path::Path::new(&path_name);
+            // Opening the database or the config would create them in a new,
+            // empty chat directory
+            if !path.exists() {
+                eprintln!(
+                    "No model found at {}. Is {} the id of a chat the bot has learned from?",
+                    path_name, args[2]
+                );
+                std::process::exit(1);
+            }
+            let database = SqliteDB::new(path).await?;
+            let config = get_config(&args[2]).await?;
+
+            let mut builder = configure(Markov::builder(Arc::new(database)), &config);
+            if let Some(position) = args.iter().position(|arg| arg == "--seed") {
+                let seed = args.get(position + 1).ok_or("--seed needs a value")?;
+                builder = builder.seed(seed.parse()?);
+            }
+
+            println!("{}", builder.build().await?.generate().await?);
+            return Ok(());
+        }
+
         if args.len() > 1 {
             use std::time::Instant;
             let now = Instant::now();
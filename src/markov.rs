@@ -3,16 +3,37 @@ use super::database::Database;
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use futures::future::try_join_all;
-use futures::stream::{futures_unordered::FuturesUnordered, StreamExt};
+use futures::stream::{futures_unordered::FuturesUnordered, StreamExt, TryStreamExt};
 use std::fs::OpenOptions;
+use std::future::Future;
 use std::io::{prelude::*, BufReader};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-pub mod macros;
+pub mod classes;
+pub mod constraints;
+pub mod error;
+pub mod fingerprint;
+pub mod generator;
+pub mod limits;
+pub mod postprocess;
+pub mod rank;
+pub mod sampling;
+pub mod score;
+pub mod smoothing;
 pub mod split;
-use macros::{generate, get_occurrence};
-use split::{is_punctuation, split_sentence};
+pub mod tokenizer;
+use classes::{ClassPolicies, ClassPolicy, TokenClass};
+use constraints::Constraints;
+use error::GenerationError;
+use fingerprint::{fingerprints, line_hash};
+use generator::{Generator, Token};
+use limits::Limits;
+use postprocess::PostProcessing;
+use rank::{Ranker, RankerType, DEFAULT_CANDIDATES};
+use sampling::Sampling;
+use smoothing::{hybrid, interpolate, normalize};
+use tokenizer::{Tokenizer, TokenizerType};
 
 const START_KEYWORD: (&str, &str) = ("start", "");
 const END_KEYWORD: (&str, &str) = ("end", "");
@@ -20,18 +41,126 @@ const END_KEYWORD: (&str, &str) = ("end", "");
 const START_INDEX: u64 = 2;
 const END_INDEX: u64 = 1;
 
+/// Longest context, in words, that is stored for `MarkovType::NGram`.
+pub const MAX_NGRAM_ORDER: usize = 5;
+/// A context with less weight than this is backed off to a shorter one.
+const NGRAM_BACKOFF_THRESHOLD: f64 = 2.0;
+
+/// Who lines are recorded as written by when the chat doesn't keep track of
+/// authors, so a retention period can still take them back exactly. No
+/// Telegram user has this id.
+pub const ANONYMOUS_USER: u64 = 0;
+
+/// Weight below which `Markov::maintain` prunes an n-gram.
+const PRUNE_WEIGHT: f64 = 0.05;
+
 const DEFAULT_HYBRID_THRESHOLD: u64 = 10;
+pub const DEFAULT_HALF_LIFE_DAYS: u64 = 0;
 pub const DEFAULT_MARKOV_TYPE: MarkovType = MarkovType::Hybrid(DEFAULT_HYBRID_THRESHOLD);
 pub const DEFAULT_REPLY_MODE: ReplyMode = ReplyMode::Reply;
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 
 #[derive(Serialize, Deserialize, Clone, Copy)]
-#[serde(tag = "type", content = "hybrid-threshold")]
+#[serde(try_from = "MarkovTypeToml", into = "MarkovTypeToml")]
 pub enum MarkovType {
     Single(u64),
     Double(u64),
     Hybrid(u64),
+    /// Uses the previous `n` words as context (1 to `MAX_NGRAM_ORDER`), falling
+    /// back to shorter contexts when a longer one has too little data.
+    NGram(u64),
+    /// Blends the double and single lookups with interpolated absolute discounting.
+    /// The value is the discount in hundredths, e.g. 75 discounts each count by 0.75.
+    Interpolated(u64),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+enum MarkovKind {
+    Single,
+    Double,
+    Hybrid,
+    NGram,
+    Interpolated,
+}
+
+/// How a `MarkovType` is written in a config. `NGram` and `Interpolated` keep
+/// their order and discount under keys of their own.
+#[derive(Serialize, Deserialize)]
+struct MarkovTypeToml {
+    #[serde(rename = "type")]
+    kind: MarkovKind,
+    #[serde(
+        rename = "hybrid-threshold",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    hybrid_threshold: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    order: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    discount: Option<u64>,
+}
+
+impl TryFrom<MarkovTypeToml> for MarkovType {
+    type Error = String;
+
+    fn try_from(toml: MarkovTypeToml) -> Result<Self, Self::Error> {
+        let value = match toml.kind {
+            // Written under hybrid-threshold by earlier versions
+            MarkovKind::NGram => toml.order.or(toml.hybrid_threshold),
+            MarkovKind::Interpolated => toml.discount.or(toml.hybrid_threshold),
+            _ => toml.hybrid_threshold,
+        };
+        let value = value.ok_or_else(|| match toml.kind {
+            MarkovKind::NGram => String::from("missing field `order`"),
+            MarkovKind::Interpolated => String::from("missing field `discount`"),
+            _ => String::from("missing field `hybrid-threshold`"),
+        })?;
+
+        let markov_type = match toml.kind {
+            MarkovKind::Single => MarkovType::Single(value),
+            MarkovKind::Double => MarkovType::Double(value),
+            MarkovKind::Hybrid => MarkovType::Hybrid(value),
+            MarkovKind::NGram => MarkovType::NGram(value),
+            MarkovKind::Interpolated => MarkovType::Interpolated(value),
+        };
+        markov_type.validate()?;
+        Ok(markov_type)
+    }
+}
+
+impl MarkovType {
+    /// Checks for values the chain can't use.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            MarkovType::Interpolated(discount) if *discount > 100 => Err(format!(
+                "discount is in hundredths and can be at most 100, not {}",
+                discount
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl From<MarkovType> for MarkovTypeToml {
+    fn from(markov_type: MarkovType) -> Self {
+        let toml = |kind, hybrid_threshold, order, discount| MarkovTypeToml {
+            kind,
+            hybrid_threshold,
+            order,
+            discount,
+        };
+        match markov_type {
+            MarkovType::Single(value) => toml(MarkovKind::Single, Some(value), None, None),
+            MarkovType::Double(value) => toml(MarkovKind::Double, Some(value), None, None),
+            MarkovType::Hybrid(value) => toml(MarkovKind::Hybrid, Some(value), None, None),
+            MarkovType::NGram(value) => toml(MarkovKind::NGram, None, Some(value), None),
+            MarkovType::Interpolated(value) => {
+                toml(MarkovKind::Interpolated, None, None, Some(value))
+            }
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy)]
@@ -56,11 +185,36 @@ impl Default for ReplyMode {
 
 type DatabaseType = Arc<dyn Database + Send + Sync>;
 
+#[derive(Clone, Copy)]
+enum Direction {
+    Next,
+    Prev,
+}
+
+impl Direction {
+    /// The `k` words of `context` closest to the word being looked up.
+    fn nearest(self, context: &[u64], k: usize) -> &[u64] {
+        match self {
+            Direction::Next => &context[context.len() - k..],
+            Direction::Prev => &context[..k],
+        }
+    }
+}
+
 pub struct Markov {
     database: DatabaseType,
     markov_type: MarkovType,
     markov_chance: u64,
     reply_mode: ReplyMode,
+    constraints: Constraints,
+    limits: Limits,
+    sampling: Sampling,
+    candidates: u64,
+    ranker: Arc<dyn Ranker>,
+    tokenizer: Arc<dyn Tokenizer>,
+    classes: ClassPolicies,
+    post_processing: PostProcessing,
+    rng: Mutex<StdRng>,
 }
 
 pub struct MarkovBuilder {
@@ -68,6 +222,16 @@ pub struct MarkovBuilder {
     markov_type: MarkovType,
     markov_chance: u64,
     reply_mode: ReplyMode,
+    constraints: Constraints,
+    limits: Limits,
+    sampling: Sampling,
+    candidates: u64,
+    ranker: Arc<dyn Ranker>,
+    tokenizer: Arc<dyn Tokenizer>,
+    classes: ClassPolicies,
+    post_processing: PostProcessing,
+    half_life_days: Option<u64>,
+    rng: Option<StdRng>,
 }
 
 impl MarkovBuilder {
@@ -77,6 +241,16 @@ impl MarkovBuilder {
             markov_type: MarkovType::default(),
             markov_chance: 10,
             reply_mode: ReplyMode::default(),
+            constraints: Constraints::default(),
+            limits: Limits::default(),
+            sampling: Sampling::default(),
+            candidates: DEFAULT_CANDIDATES,
+            ranker: RankerType::default().build(),
+            tokenizer: TokenizerType::default().build(),
+            classes: ClassPolicies::default(),
+            post_processing: PostProcessing::default(),
+            half_life_days: None,
+            rng: None,
         }
     }
 
@@ -95,15 +269,95 @@ impl MarkovBuilder {
         self
     }
 
+    pub fn constraints(mut self, constraints: Constraints) -> MarkovBuilder {
+        self.constraints = constraints;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> MarkovBuilder {
+        self.limits = limits;
+        self
+    }
+
+    pub fn sampling(mut self, sampling: Sampling) -> MarkovBuilder {
+        self.sampling = sampling;
+        self
+    }
+
+    /// How many sentences are generated for the ranker to pick from.
+    pub fn candidates(mut self, candidates: u64) -> MarkovBuilder {
+        self.candidates = candidates;
+        self
+    }
+
+    pub fn ranker(mut self, ranker: Arc<dyn Ranker>) -> MarkovBuilder {
+        self.ranker = ranker;
+        self
+    }
+
+    pub fn tokenizer(mut self, tokenizer: Arc<dyn Tokenizer>) -> MarkovBuilder {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    /// Whether URLs, mentions and the other token classes are learned,
+    /// dropped or replaced by a placeholder.
+    pub fn classes(mut self, classes: ClassPolicies) -> MarkovBuilder {
+        self.classes = classes;
+        self
+    }
+
+    /// Cleanup applied to every generated sentence.
+    pub fn post_processing(mut self, post_processing: PostProcessing) -> MarkovBuilder {
+        self.post_processing = post_processing;
+        self
+    }
+
+    /// Makes learned counts fade by half every `days` days. 0 keeps them forever.
+    /// Left unset, the model keeps whatever half-life it had.
+    pub fn half_life_days(mut self, days: u64) -> MarkovBuilder {
+        self.half_life_days = Some(days);
+        self
+    }
+
+    /// Makes every random choice reproducible: the same model and seed always
+    /// give the same output.
+    pub fn seed(mut self, seed: u64) -> MarkovBuilder {
+        self.rng = Some(StdRng::seed_from_u64(seed));
+        self
+    }
+
+    pub fn rng(mut self, rng: StdRng) -> MarkovBuilder {
+        self.rng = Some(rng);
+        self
+    }
+
     pub async fn build(self) -> Result<Markov, Error> {
+        self.markov_type.validate()?;
+        self.constraints.validate()?;
+        self.sampling.validate()?;
         self.database.add_word(END_KEYWORD).await?;
         self.database.add_word(START_KEYWORD).await?;
-        Ok(Markov {
+        if let Some(days) = self.half_life_days {
+            self.database.set_half_life(days_to_seconds(days)?).await?;
+        }
+        let markov = Markov {
             database: self.database,
             markov_type: self.markov_type,
             markov_chance: self.markov_chance,
             reply_mode: self.reply_mode,
-        })
+            constraints: self.constraints,
+            limits: self.limits,
+            sampling: self.sampling,
+            candidates: self.candidates,
+            ranker: self.ranker,
+            tokenizer: self.tokenizer,
+            classes: self.classes,
+            post_processing: self.post_processing,
+            rng: Mutex::new(self.rng.unwrap_or_else(StdRng::from_entropy)),
+        };
+        markov.migrate_contributions().await?;
+        Ok(markov)
     }
 }
 
@@ -114,10 +368,20 @@ impl Markov {
             markov_type: MarkovType::default(),
             markov_chance: 10,
             reply_mode: ReplyMode::default(),
+            constraints: Constraints::default(),
+            limits: Limits::default(),
+            sampling: Sampling::default(),
+            candidates: DEFAULT_CANDIDATES,
+            ranker: RankerType::default().build(),
+            tokenizer: TokenizerType::default().build(),
+            classes: ClassPolicies::default(),
+            post_processing: PostProcessing::default(),
+            rng: Mutex::new(StdRng::from_entropy()),
         };
 
         markov.database.add_word(END_KEYWORD).await?;
         markov.database.add_word(START_KEYWORD).await?;
+        markov.migrate_contributions().await?;
         Ok(markov)
     }
 
@@ -125,56 +389,75 @@ impl Markov {
         MarkovBuilder::new(database)
     }
 
+    pub fn tokenizer(&self) -> &dyn Tokenizer {
+        self.tokenizer.as_ref()
+    }
+
     pub fn chance(&self) -> bool {
         if self.markov_chance == 0 {
             return false;
         }
 
-        let mut rng = thread_rng();
+        let mut rng = self.rng.lock().unwrap();
         if rng.gen_range(1..=self.markov_chance) == 1 {
             return true;
         }
         false
     }
 
-    pub async fn append_line(&self, line: &str) -> Result<(), Error> {
-        let split = split_sentence(line);
-
-        let (mut prev, mut curr) = (START_KEYWORD, START_KEYWORD);
-        let mut next = START_KEYWORD;
-
-        let length = split.len();
-        let mut index = 0;
+    /// The tokens of `line` that get learned, leaving out dropped classes.
+    fn learnable(&self, line: &str) -> Vec<String> {
+        self.tokenizer
+            .tokenize(line)
+            .into_iter()
+            .filter(|word| self.classes.policy(self.tokenizer.classify(word)) != ClassPolicy::Drop)
+            .collect()
+    }
 
-        let mut futures = split
-            .iter()
-            .map(|word| {
-                index += 1;
-                prev = curr;
-                curr = next;
-                if index == length {
-                    next = ("last", word);
-                } else if index == 1 {
-                    next = ("first", word);
-                } else {
-                    next = ("middle", word);
-                }
-                self.append_word(prev, curr, next)
-            })
-            .collect::<FuturesUnordered<_>>();
+    pub async fn append_line(&self, line: &str) -> Result<(), Error> {
+        self.learn(&self.learnable(line)).await?;
+        Ok(())
+    }
 
-        if next == START_KEYWORD {
+    /// Learns an already tokenized line and returns the ids of its words.
+    async fn learn(&self, split: &[String]) -> Result<Vec<u64>, Error> {
+        if split.is_empty() {
             //This will never occur with teloxide
             //It just did
             //panic!("Empty line");
-            return Ok(());
+            return Ok(vec![]);
+        }
+
+        let length = split.len();
+        let mut words = vec![];
+
+        for (index, word) in split.iter().enumerate() {
+            let class = self.tokenizer.classify(word).map(|class| class.tag());
+            words.push(
+                self.database
+                    .add_classified_word((keyword(index, length), word), class)
+                    .await?,
+            );
         }
+        let indices = padded(&words);
+
+        let mut futures = (MAX_NGRAM_ORDER..indices.len())
+            .map(|index| self.append_ngrams(&indices[..=index]))
+            .collect::<FuturesUnordered<_>>();
 
-        futures.push(self.append_word(curr, next, END_KEYWORD));
         while let Some(res) = futures.next().await {
             res?;
         }
-        Ok(())
+
+        // Runs are only fingerprinted for the originality check, at the one
+        // length it looks for
+        if let Some(length) = self.constraints.checked_run() {
+            for hash in fingerprints(split, length) {
+                self.database.increment_fingerprint(hash).await?;
+            }
+        }
+        self.database.increment_line(line_hash(split)).await?;
+        Ok(words)
     }
 
     pub async fn append_newlines(&self, lines: &str) -> Result<(), Error> {
@@ -187,46 +470,428 @@ impl Markov {
         Ok(())
     }
 
-    async fn next_word(&self, index1: u64, index2: u64) -> Result<u64, Error> {
-        let database = &self.database;
+    /// Learns `line` and remembers that `user_id` wrote it. Only the ids of its
+    /// words are kept, not the text.
+    pub async fn append_attributed(&self, line: &str, user_id: u64) -> Result<(), Error> {
+        let words = self.learn(&self.learnable(line)).await?;
+        if words.is_empty() {
+            return Ok(());
+        }
+        self.database.add_contribution(user_id, &words, None).await
+    }
+
+    /// Turns attributed lines that older versions stored as text into word ids.
+    async fn migrate_contributions(&self) -> Result<(), Error> {
+        for (user_id, line, timestamp) in self.database.legacy_contributions().await? {
+            if let Some(words) = self.find_line(&line).await? {
+                self.database
+                    .add_contribution(user_id, &words, Some(timestamp))
+                    .await?;
+            }
+        }
+        self.database.remove_legacy_contributions().await
+    }
+
+    /// Removes every line `user_id` contributed. Returns how many were removed.
+    pub async fn erase_user(&self, user_id: u64) -> Result<u64, Error> {
+        let mut removed = 0;
+        for words in self.database.get_contributions(user_id).await? {
+            if self.remove_words(&words).await? {
+                removed += 1;
+            }
+        }
+
+        self.database.remove_contributions(user_id).await?;
+        Ok(removed)
+    }
+
+    /// The ids of the words `line` was learned as, if it was learned.
+    async fn find_line(&self, line: &str) -> Result<Option<Vec<u64>>, Error> {
+        // The line may have been learned before its links, mentions and such
+        // were set to be dropped, or after, so every way it could have been
+        // learned is tried
+        let tokens = self.tokenizer.tokenize(line);
+        let unclassified: Vec<String> = tokens
+            .iter()
+            .filter(|word| self.tokenizer.classify(word).is_none())
+            .cloned()
+            .collect();
+        let mut variants = vec![tokens, self.learnable(line), unclassified];
+        variants.dedup();
+
+        for split in variants {
+            if let Some(words) = self.find_words(&split).await? {
+                return Ok(Some(words));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The ids of `split` if it was learned as a line of its own.
+    async fn find_words(&self, split: &[String]) -> Result<Option<Vec<u64>>, Error> {
+        if split.is_empty() {
+            return Ok(None);
+        }
+
+        let length = split.len();
+        let mut words = vec![];
+        for (index, word) in split.iter().enumerate() {
+            match self
+                .database
+                .get_index((keyword(index, length), word))
+                .await?
+            {
+                Some(index) => words.push(index),
+                None => return Ok(None),
+            }
+        }
+
+        // Every n-gram of a line that was never said can still have been learned
+        // from other lines, so the line's own hash has to be there too
+        if self.database.tracks_lines().await? && !self.database.has_line(line_hash(split)).await? {
+            return Ok(None);
+        }
+        Ok(self.is_learned(&words).await?.then_some(words))
+    }
+
+    /// Whether a line made of `words` is still in the model. Only the triples
+    /// are checked, as every `MarkovType` stores them.
+    async fn is_learned(&self, words: &[u64]) -> Result<bool, Error> {
+        let indices = padded(words);
+        for window in indices[MAX_NGRAM_ORDER - 2..].windows(3) {
+            let occurrences = self
+                .database
+                .get_double_occurrences(window[0], window[1])
+                .await?;
+            if !occurrences.iter().any(|(index, _)| *index == window[2]) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Takes back exactly what `append_line` added for `line`, deleting counts
+    /// that drop to zero and words no other line uses. Returns false and leaves
+    /// the model alone if the line was never learned.
+    pub async fn remove_line(&self, line: &str) -> Result<bool, Error> {
+        match self.find_line(line).await? {
+            Some(words) => {
+                self.remove_learned(&words).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Removes `line` along with the record of `user_id` writing it, so a
+    /// later `erase_user` doesn't take it back a second time.
+    pub async fn remove_attributed(&self, line: &str, user_id: u64) -> Result<bool, Error> {
+        match self.find_line(line).await? {
+            Some(words) => {
+                self.remove_learned(&words).await?;
+                self.database.remove_contribution(user_id, &words).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Like `remove_line`, for a line already turned into word ids.
+    async fn remove_words(&self, words: &[u64]) -> Result<bool, Error> {
+        if words.is_empty() || !self.is_learned(words).await? {
+            return Ok(false);
+        }
+        self.remove_learned(words).await?;
+        Ok(true)
+    }
+
+    async fn remove_learned(&self, words: &[u64]) -> Result<(), Error> {
+        let mut split = vec![];
+        for word in words {
+            split.push(self.database.get_word(*word).await?);
+        }
+
+        let indices = padded(words);
+        for index in MAX_NGRAM_ORDER..indices.len() {
+            self.remove_ngrams(&indices[..=index]).await?;
+        }
+
+        if let Some(length) = self.constraints.checked_run() {
+            for hash in fingerprints(&split, length) {
+                self.database.decrement_fingerprint(hash).await?;
+            }
+        }
+        self.database.decrement_line(line_hash(&split)).await?;
+
+        for index in words {
+            self.database.remove_orphan(*index).await?;
+        }
+        Ok(())
+    }
+
+    /// The counterpart of `append_newlines`. Returns true if any line was removed.
+    pub async fn remove_newlines(&self, lines: &str) -> Result<bool, Error> {
+        let mut removed = false;
+        for n in lines.split('\n') {
+            removed |= self.remove_line(n.trim()).await?;
+        }
+
+        Ok(removed)
+    }
+
+    /// How many lines the model has learned, weighed by their decay.
+    pub async fn lines(&self) -> Result<f64, Error> {
+        let starts = self
+            .database
+            .get_double_occurrences(START_INDEX, START_INDEX)
+            .await?;
+        Ok(total_occurrences(&starts))
+    }
+
+    /// Shrinks decayed weights for good and prunes n-grams that have all but
+    /// faded away. Does nothing for a model without a half-life.
+    pub async fn maintain(&self) -> Result<(), Error> {
+        self.database.maintain(PRUNE_WEIGHT).await
+    }
+
+    /// Forgets everything learned more than `days` days ago. Recorded lines,
+    /// `ANONYMOUS_USER`'s included, are taken back exactly, then any n-gram not
+    /// seen since is dropped. A line learned without a record stays in every
+    /// n-gram that was seen again later.
+    pub async fn purge(&self, days: u64) -> Result<(), Error> {
+        let max_age = days_to_seconds(days)?;
+
+        for words in self.database.get_contributions_older(max_age).await? {
+            self.remove_words(&words).await?;
+        }
+        self.database.purge(max_age).await
+    }
+
+    pub async fn last_purge(&self) -> Result<Option<SystemTime>, Error> {
+        Ok(self
+            .database
+            .last_purge()
+            .await?
+            .map(|time| UNIX_EPOCH + Duration::from_secs(time)))
+    }
+
+    /// Picks the word after `history`, or `None` if nothing was ever seen there.
+    async fn next_word(&self, history: &[u64]) -> Result<Option<u64>, Error> {
+        let distribution = self.distribution(Direction::Next, history).await?;
+        self.choose_distribution(&distribution)
+    }
+
+    /// Picks the word before `future`, or `None` if nothing was ever seen there.
+    async fn prev_word(&self, future: &[u64]) -> Result<Option<u64>, Error> {
+        let distribution = self.distribution(Direction::Prev, future).await?;
+        self.choose_distribution(&distribution)
+    }
+
+    /// Probabilities of every word that can follow (or precede) `context`
+    /// under the chain's `MarkovType`. `context` is in sentence order.
+    async fn distribution(
+        &self,
+        direction: Direction,
+        context: &[u64],
+    ) -> Result<Vec<(u64, f64)>, Error> {
+        if context.len() < 2 {
+            return Ok(normalize(&self.lookup(direction, context).await?));
+        }
+
+        let single = direction.nearest(context, 1);
+        let double = direction.nearest(context, 2);
 
         match self.markov_type {
-            MarkovType::Single(_) => Ok(get_occurrence!(database, index2)),
-            MarkovType::Double(_) => Ok(get_occurrence!(database, index1, index2).0),
-            MarkovType::Hybrid(t) => {
-                let tuple = get_occurrence!(database, index1, index2);
-                if tuple.1 < t {
-                    Ok(get_occurrence!(database, index2))
-                } else {
-                    Ok(tuple.0)
+            MarkovType::Single(_) => Ok(normalize(&self.lookup(direction, single).await?)),
+            MarkovType::Double(_) => Ok(normalize(&self.lookup(direction, double).await?)),
+            MarkovType::Hybrid(t) => Ok(hybrid(
+                &self.lookup(direction, double).await?,
+                &self.lookup(direction, single).await?,
+                t,
+            )),
+            MarkovType::NGram(n) => {
+                let order = (n as usize).clamp(1, MAX_NGRAM_ORDER).min(context.len());
+
+                for k in (2..=order).rev() {
+                    let vec = self
+                        .lookup(direction, direction.nearest(context, k))
+                        .await?;
+                    if total_occurrences(&vec) >= NGRAM_BACKOFF_THRESHOLD {
+                        return Ok(normalize(&vec));
+                    }
                 }
+                Ok(normalize(&self.lookup(direction, single).await?))
             }
+            MarkovType::Interpolated(d) => Ok(interpolate(
+                &self.lookup(direction, double).await?,
+                &self.lookup(direction, single).await?,
+                d as f64 / 100.0,
+            )),
         }
     }
 
-    async fn prev_word(&self, index1: u64, index2: u64) -> Result<u64, Error> {
+    /// Weights for a context of any length up to `MAX_NGRAM_ORDER`.
+    async fn lookup(
+        &self,
+        direction: Direction,
+        context: &[u64],
+    ) -> Result<Vec<(u64, f64)>, Error> {
         let database = &self.database;
 
-        match self.markov_type {
-            MarkovType::Single(_) => Ok(get_occurrence!(reverse database, index1)),
-            MarkovType::Double(_) => Ok(get_occurrence!(reverse database, index1, index2).0),
-            MarkovType::Hybrid(t) => {
-                let tuple = get_occurrence!(reverse database, index1, index2);
-                if tuple.1 < t {
-                    Ok(get_occurrence!(reverse database, index1))
-                } else {
-                    Ok(tuple.0)
-                }
+        match (direction, context.len()) {
+            (Direction::Next, 1) => database.get_single_occurrences(context[0]).await,
+            (Direction::Next, 2) => {
+                database
+                    .get_double_occurrences(context[0], context[1])
+                    .await
             }
+            (Direction::Next, _) => database.get_ngram_occurrences(context).await,
+            (Direction::Prev, 1) => database.get_prev_single_occurrences(context[0]).await,
+            (Direction::Prev, 2) => {
+                database
+                    .get_prev_double_occurrences(context[0], context[1])
+                    .await
+            }
+            (Direction::Prev, _) => database.get_prev_ngram_occurrences(context).await,
         }
     }
 
-    async fn get_word(&self, index: u64) -> Result<String, Error> {
-        self.database.get_word(index).await
+    async fn get_token(&self, index: u64) -> Result<Token, Error> {
+        let (word, class) = self.database.get_classified_word(index).await?;
+        Ok(Token {
+            index,
+            word,
+            class: class.as_deref().and_then(TokenClass::from_tag),
+        })
     }
 
     pub async fn generate(&self) -> Result<String, Error> {
-        Ok(generate!(self, START_INDEX, START_INDEX, END_INDEX))
+        let words = self
+            .best_of(&[], || self.collect(Generator::new(self)))
+            .await?;
+        Ok(self
+            .post_processing
+            .apply(self.tokenizer.detokenize(&words)))
+    }
+
+    /// Generates a sentence that contains `phrase`, growing it in both
+    /// directions from a place where the phrase was learned.
+    pub async fn generate_from(&self, phrase: &str) -> Result<String, Error> {
+        let tokens = self.tokenizer.tokenize(phrase);
+        let seeds = self.find_phrase(&tokens).await?;
+        if seeds.is_empty() {
+            return Err(GenerationError::UnknownPhrase(phrase.to_owned()).into());
+        }
+
+        let words = self
+            .attempt(Instant::now(), &tokens, || async {
+                let seed = {
+                    let mut rng = self.rng.lock().unwrap();
+                    seeds.choose(&mut *rng).unwrap().clone()
+                };
+                self.collect(Generator::bidirectional(self, seed)).await
+            })
+            .await?;
+        Ok(self
+            .post_processing
+            .apply(self.tokenizer.detokenize(&words)))
+    }
+
+    /// Finishes a sentence that starts with `text`, walking on from its last one
+    /// or two words. `text` is kept as it was written.
+    pub async fn continue_from(&self, text: &str) -> Result<String, Error> {
+        let tokens = self.tokenizer.tokenize(text);
+        let mut seeds = vec![];
+
+        for length in (1..=tokens.len().min(2)).rev() {
+            for seed in self.find_phrase(&tokens[tokens.len() - length..]).await? {
+                let next = self
+                    .database
+                    .get_single_occurrences(seed[seed.len() - 1])
+                    .await?;
+                if next.iter().any(|item| item.0 != END_INDEX) {
+                    seeds.push(seed);
+                }
+            }
+
+            if !seeds.is_empty() {
+                break;
+            }
+        }
+
+        if seeds.is_empty() {
+            let last = tokens.last().cloned().unwrap_or_default();
+            return Err(GenerationError::UnknownPhrase(last).into());
+        }
+
+        let words = self
+            .attempt(Instant::now(), &tokens, || async {
+                let seed = {
+                    let mut rng = self.rng.lock().unwrap();
+                    seeds.choose(&mut *rng).unwrap().clone()
+                };
+                let mut words = tokens.clone();
+                words.extend(self.collect(Generator::forward(self, seed)).await?);
+                Ok(words)
+            })
+            .await?;
+        let continuation = self.post_processing.apply_part(
+            self.tokenizer.detokenize(&words[tokens.len()..]),
+            false,
+            true,
+        );
+        Ok(self.tokenizer.join(text, &continuation))
+    }
+
+    /// Makes up the beginning of a sentence that leads into `text`, walking
+    /// backwards from its first one or two words. `text` is kept as it was written.
+    pub async fn precede(&self, text: &str) -> Result<String, Error> {
+        let tokens = self.tokenizer.tokenize(text);
+        let mut seeds = vec![];
+
+        for length in (1..=tokens.len().min(2)).rev() {
+            for seed in self.find_phrase(&tokens[..length]).await? {
+                let prev = if seed.len() < 2 {
+                    self.database.get_prev_single_occurrences(seed[0]).await?
+                } else {
+                    self.database
+                        .get_prev_double_occurrences(seed[0], seed[1])
+                        .await?
+                };
+                if prev.iter().any(|item| item.0 != START_INDEX) {
+                    seeds.push(seed);
+                }
+            }
+
+            if !seeds.is_empty() {
+                break;
+            }
+        }
+
+        if seeds.is_empty() {
+            let first = tokens.first().cloned().unwrap_or_default();
+            return Err(GenerationError::UnknownPhrase(first).into());
+        }
+
+        let words = self
+            .attempt(Instant::now(), &tokens, || async {
+                let seed = {
+                    let mut rng = self.rng.lock().unwrap();
+                    seeds.choose(&mut *rng).unwrap().clone()
+                };
+                let mut words = self.collect(Generator::backward(self, seed)).await?;
+                words.reverse();
+                words.extend(tokens.iter().cloned());
+                Ok(words)
+            })
+            .await?;
+        let beginning = self.post_processing.apply_part(
+            self.tokenizer
+                .detokenize(&words[..words.len() - tokens.len()]),
+            true,
+            false,
+        );
+        Ok(self.tokenizer.join(&beginning, text))
     }
 
     pub async fn generate_reply(&self, line: &str) -> Result<String, Error> {
@@ -235,95 +900,355 @@ impl Markov {
                 return Ok("".to_owned());
             }
             ReplyMode::Random => {
-                return Ok(generate!(self, START_INDEX, START_INDEX, END_INDEX));
+                return self.generate().await;
             }
             _ => {}
         };
 
-        let split = split_sentence(line);
-        let database = &self.database;
+        let input = self.tokenizer.tokenize(line);
+        let words = self.best_of(&input, || self.reply_words(line)).await?;
+        let sentence = self
+            .post_processing
+            .apply(self.tokenizer.detokenize(&words));
+
+        match &self.reply_mode {
+            ReplyMode::ReplyUnique => {
+                if line.to_lowercase() == sentence.to_lowercase() {
+                    self.generate().await
+                } else {
+                    Ok(sentence)
+                }
+            }
+            _ => Ok(sentence),
+        }
+    }
+
+    /// Generates the configured number of candidates and keeps the one the
+    /// ranker likes best. `input` is the message being answered, if any.
+    async fn best_of<F, Fut>(&self, input: &[String], walk: F) -> Result<Vec<String>, Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<Vec<String>, Error>>,
+    {
+        let started = Instant::now();
+        let mut best: Option<(f64, Vec<String>)> = None;
+        let mut error = None;
+
+        // Without input a ranker that compares against it can't tell the
+        // candidates apart
+        let candidates = if input.is_empty() && self.ranker.uses_input() {
+            1
+        } else {
+            self.candidates.max(1)
+        };
+
+        for _ in 0..candidates {
+            let words = match self.attempt(started, &[], &walk).await {
+                Ok(words) => words,
+                Err(e) => {
+                    let timed_out = matches!(
+                        e.downcast_ref::<GenerationError>(),
+                        Some(GenerationError::Timeout(_))
+                    );
+                    error = Some(e);
+                    if timed_out {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            let rank = self.ranker.rank(self, &words, input).await?;
+            if best.as_ref().is_none_or(|best| rank > best.0) {
+                best = Some((rank, words));
+            }
+        }
+
+        match (best, error) {
+            (Some((_, words)), _) => Ok(words),
+            (None, Some(e)) => Err(e),
+            (None, None) => Err(GenerationError::Constraints.into()),
+        }
+    }
+
+    /// Runs `walk` until its words fit the constraints or the retries run out.
+    /// `given` are the words the user supplied, which the walk builds around.
+    /// The time limit counts from `started`.
+    async fn attempt<F, Fut>(
+        &self,
+        started: Instant,
+        given: &[String],
+        walk: F,
+    ) -> Result<Vec<String>, Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<Vec<String>, Error>>,
+    {
+        let mut error = GenerationError::Constraints;
+
+        for _ in 0..=self.constraints.retries {
+            match self.within_limit(started, walk()).await {
+                Ok(words)
+                    if self.constraints.accepts(&words)
+                        && self.is_original(&words, given).await? =>
+                {
+                    return Ok(words);
+                }
+                Ok(_) => {}
+                Err(e) => error = self.retryable(e, started)?,
+            }
+        }
+        Err(error.into())
+    }
+
+    /// Runs `walk` for whatever is left of the time limit counted from `started`,
+    /// so a walk is cut short even in the middle of a word.
+    async fn within_limit<Fut>(&self, started: Instant, walk: Fut) -> Result<Vec<String>, Error>
+    where
+        Fut: Future<Output = Result<Vec<String>, Error>>,
+    {
+        let Some(timeout) = self.limits.timeout() else {
+            return walk.await;
+        };
+
+        let remaining = timeout.saturating_sub(started.elapsed());
+        match tokio::time::timeout(remaining, walk).await {
+            Ok(result) => result,
+            Err(_) => Err(GenerationError::Timeout(timeout).into()),
+        }
+    }
+
+    /// Checks that no run of words longer than the allowed maximum was copied
+    /// from a learned line. Runs that lie within `given`, the words the user
+    /// asked for, don't count against the sentence.
+    async fn is_original(&self, words: &[String], given: &[String]) -> Result<bool, Error> {
+        let Some(length) = self.constraints.checked_run() else {
+            return Ok(true);
+        };
+
+        let given = fingerprints(given, length);
+        for hash in fingerprints(words, length) {
+            if !given.contains(&hash) && self.database.has_fingerprint(hash).await? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Finds every run of word ids that spells out `tokens` and was learned in
+    /// that order.
+    async fn find_phrase(&self, tokens: &[String]) -> Result<Vec<Vec<u64>>, Error> {
+        let mut sequences: Vec<Vec<u64>> = vec![vec![]];
+
+        for token in tokens {
+            let candidates = self.database.get_case_insensitive(token).await?;
+            let mut extended = vec![];
+
+            for sequence in &sequences {
+                let allowed: Option<Vec<u64>> = match sequence.len() {
+                    0 => None,
+                    1 => Some(self.database.get_single_occurrences(sequence[0]).await?),
+                    n => Some(
+                        self.database
+                            .get_double_occurrences(sequence[n - 2], sequence[n - 1])
+                            .await?,
+                    ),
+                }
+                .map(|vec| vec.into_iter().map(|item| item.0).collect());
+
+                for (index, _) in &candidates {
+                    if allowed.as_ref().is_none_or(|vec| vec.contains(index)) {
+                        let mut sequence = sequence.clone();
+                        sequence.push(*index);
+                        extended.push(sequence);
+                    }
+                }
+            }
+
+            sequences = extended;
+            if sequences.is_empty() {
+                break;
+            }
+        }
+
+        sequences.retain(|sequence| !sequence.is_empty());
+        Ok(sequences)
+    }
+
+    /// Lets a walk that hit the token limit or a cycle be retried, as long as
+    /// the whole generation is still within its time limit.
+    fn retryable(&self, error: Error, started: Instant) -> Result<GenerationError, Error> {
+        if let Some(timeout) = self.limits.timeout() {
+            if started.elapsed() > timeout {
+                return Err(GenerationError::Timeout(timeout).into());
+            }
+        }
+
+        match error.downcast::<GenerationError>() {
+            Ok(e) => match *e {
+                GenerationError::TokenLimit(_) | GenerationError::Cycle => Ok(*e),
+                e => Err(e.into()),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn reply_words(&self, line: &str) -> Result<Vec<String>, Error> {
+        let split = self.tokenizer.tokenize(line);
 
         let word;
         {
-            let mut rng = thread_rng();
-            word = split.choose(&mut rng).unwrap();
+            let mut rng = self.rng.lock().unwrap();
+            word = split.choose(&mut *rng).ok_or(GenerationError::EmptyInput)?;
         }
 
         let vec = self.database.get_case_insensitive(word).await?;
-        let index;
-        let keyword;
+        let (index, keyword);
         {
-            let mut rng = thread_rng();
-            if let Some(tuple) = vec.choose(&mut rng) {
-                index = tuple.0;
-                keyword = tuple.1.to_owned();
+            let mut rng = self.rng.lock().unwrap();
+            if let Some(tuple) = vec.choose(&mut *rng) {
+                (index, keyword) = tuple.clone();
             } else {
                 let err: Error = String::from("Could not find similar words!").into();
                 return Err(err);
             }
         }
-        let sentence;
 
-        if keyword == "first" {
-            sentence = generate!(reply self, index, START_INDEX, END_INDEX);
-        } else if keyword == "last" {
-            sentence = generate!(reverse self, index, END_INDEX, START_INDEX);
-        } else {
-            let second = get_occurrence!(database, index);
-            let mut first_half = generate!(reverse self, index, second, START_INDEX);
-            let second_half = generate!(reply self, second, index, END_INDEX);
-
-            let is_punc1 = {
-                let x = first_half.clone().chars().next_back();
-                match x {
-                    Some(x) => is_punctuation(Ok(x)),
-                    None => true,
-                }
-            };
+        // A word that starts or ends a sentence only needs to be grown the
+        // other way, with the sentence boundary as part of its context
+        match keyword.as_str() {
+            "first" => {
+                let mut context = vec![START_INDEX; MAX_NGRAM_ORDER];
+                context.push(index);
+                let mut words: Vec<String> = self
+                    .shown(self.get_token(index).await?)
+                    .into_iter()
+                    .collect();
+                words.extend(self.collect(Generator::forward(self, context)).await?);
+                Ok(words)
+            }
+            "last" => {
+                let mut words = self
+                    .collect(Generator::backward(self, vec![index, END_INDEX]))
+                    .await?;
+                words.reverse();
+                words.extend(self.shown(self.get_token(index).await?));
+                Ok(words)
+            }
+            _ => {
+                self.collect(Generator::bidirectional(self, vec![index]))
+                    .await
+            }
+        }
+    }
 
-            let is_punc2 = {
-                let x = second_half.clone().chars().next();
-                match x {
-                    Some(x) => is_punctuation(Ok(x)),
-                    None => true,
-                }
-            };
+    /// How a generated token comes out under the class policies, if at all.
+    fn shown(&self, token: Token) -> Option<String> {
+        match self.classes.policy(token.class) {
+            ClassPolicy::Learn => Some(token.word),
+            ClassPolicy::Drop => None,
+            ClassPolicy::Placeholder => token.class.map(|class| class.placeholder().to_owned()),
+        }
+    }
 
-            if !is_punc1 && !is_punc2 {
-                first_half.push(' ');
-            }
-            sentence = first_half + &second_half;
+    /// Collects the words of a walk, giving up as soon as it is too long to
+    /// pass the constraints.
+    async fn collect(&self, generator: Generator<'_>) -> Result<Vec<String>, Error> {
+        // Dropped tokens are left out before counting, so they don't use up
+        // the room under max_tokens
+        let stream = generator
+            .stream()
+            .try_filter_map(|token| async move { Ok(self.shown(token)) });
+        if self.constraints.max_tokens == 0 {
+            stream.try_collect().await
+        } else {
+            stream
+                .take(self.constraints.max_tokens as usize + 1)
+                .try_collect()
+                .await
         }
+    }
 
-        match &self.reply_mode {
-            ReplyMode::ReplyUnique => {
-                if line.to_lowercase() == sentence.to_lowercase() {
-                    Ok(generate!(self, START_INDEX, START_INDEX, END_INDEX))
-                } else {
-                    Ok(sentence)
-                }
-            }
-            _ => Ok(sentence),
+    fn choose_distribution(&self, vec: &[(u64, f64)]) -> Result<Option<u64>, Error> {
+        let vec = self.sampling.reshape(vec);
+        if vec.is_empty() {
+            return Ok(None);
         }
+        let mut rng = self.rng.lock().unwrap();
+        Ok(Some(vec.choose_weighted(&mut *rng, |item| item.1)?.0))
     }
 
-    async fn append_word(
-        &self,
-        prev: (&str, &str),
-        curr: (&str, &str),
-        next: (&str, &str),
-    ) -> Result<(), Error> {
-        let vec = try_join_all(vec![
-            self.database.add_word(prev),
-            self.database.add_word(curr),
-            self.database.add_word(next),
-        ])
-        .await?;
-        self.database.increment(vec[0], vec[1], vec[2]).await?;
+    async fn append_ngrams(&self, indices: &[u64]) -> Result<(), Error> {
+        let length = indices.len();
+        self.database
+            .increment(
+                indices[length - 3],
+                indices[length - 2],
+                indices[length - 1],
+            )
+            .await?;
+
+        // Only NGram looks further back than two words
+        if !matches!(self.markov_type, MarkovType::NGram(_)) {
+            return Ok(());
+        }
+        for order in 3..=MAX_NGRAM_ORDER.min(length - 1) {
+            self.database
+                .increment_ngram(&indices[length - order - 1..])
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn remove_ngrams(&self, indices: &[u64]) -> Result<(), Error> {
+        let length = indices.len();
+        self.database
+            .decrement(
+                indices[length - 3],
+                indices[length - 2],
+                indices[length - 1],
+            )
+            .await?;
+
+        // The line may have been learned while the chat was set to NGram
+        for order in 3..=MAX_NGRAM_ORDER.min(length - 1) {
+            self.database
+                .decrement_ngram(&indices[length - order - 1..])
+                .await?;
+        }
         Ok(())
     }
 }
 
+fn days_to_seconds(days: u64) -> Result<u64, Error> {
+    days.checked_mul(24 * 60 * 60)
+        .ok_or_else(|| format!("{} days is too long a period", days).into())
+}
+
+/// A line's word ids with the start and end markers that surround it in
+/// every n-gram.
+fn padded(words: &[u64]) -> Vec<u64> {
+    let mut indices = vec![START_INDEX; MAX_NGRAM_ORDER];
+    indices.extend_from_slice(words);
+    indices.push(END_INDEX);
+    indices
+}
+
+/// The position keyword a word is stored under in `Words`.
+fn keyword(index: usize, length: usize) -> &'static str {
+    if index + 1 == length {
+        "last"
+    } else if index == 0 {
+        "first"
+    } else {
+        "middle"
+    }
+}
+
+fn total_occurrences(vec: &[(u64, f64)]) -> f64 {
+    vec.iter().map(|item| item.1).sum()
+}
+
 pub async fn sneedov_feed(filename: &str, database: DatabaseType) -> Result<(), Error> {
     let file = OpenOptions::new().read(true).open(filename)?;
 
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_CLASS_POLICY: ClassPolicy = ClassPolicy::Learn;
+
+/// Tokens that aren't ordinary words. Each is stored with its tag in `Words`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenClass {
+    Url,
+    Mention,
+    Hashtag,
+    Command,
+    Number,
+}
+
+impl TokenClass {
+    /// Recognizes the class of a single token, if it has one.
+    pub fn of(token: &str) -> Option<TokenClass> {
+        let lowercase = token.to_lowercase();
+        let is_name =
+            |rest: &str| !rest.is_empty() && rest.chars().all(|c| c.is_alphanumeric() || c == '_');
+
+        if lowercase.contains("://")
+            || lowercase.starts_with("www.")
+            || lowercase.starts_with("t.me/")
+        {
+            Some(TokenClass::Url)
+        } else if let Some(rest) = token.strip_prefix('@') {
+            is_name(rest).then_some(TokenClass::Mention)
+        } else if let Some(rest) = token.strip_prefix('#') {
+            is_name(rest).then_some(TokenClass::Hashtag)
+        } else if let Some(rest) = token.strip_prefix('/') {
+            let command = rest.split_once('@').map_or(rest, |(command, _)| command);
+            is_name(command).then_some(TokenClass::Command)
+        } else {
+            let digits = token.strip_prefix(['-', '+']).unwrap_or(token);
+            let is_number = digits.starts_with(|c: char| c.is_ascii_digit())
+                && digits
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || c == '.' || c == ',');
+            is_number.then_some(TokenClass::Number)
+        }
+    }
+
+    pub fn tag(&self) -> &'static str {
+        match self {
+            TokenClass::Url => "url",
+            TokenClass::Mention => "mention",
+            TokenClass::Hashtag => "hashtag",
+            TokenClass::Command => "command",
+            TokenClass::Number => "number",
+        }
+    }
+
+    pub fn from_tag(tag: &str) -> Option<TokenClass> {
+        match tag {
+            "url" => Some(TokenClass::Url),
+            "mention" => Some(TokenClass::Mention),
+            "hashtag" => Some(TokenClass::Hashtag),
+            "command" => Some(TokenClass::Command),
+            "number" => Some(TokenClass::Number),
+            _ => None,
+        }
+    }
+
+    /// What `ClassPolicy::Placeholder` puts in place of the token.
+    pub fn placeholder(&self) -> &'static str {
+        match self {
+            TokenClass::Url => "[link]",
+            TokenClass::Mention => "[user]",
+            TokenClass::Hashtag => "[hashtag]",
+            TokenClass::Command => "[command]",
+            TokenClass::Number => "[number]",
+        }
+    }
+}
+
+/// What happens to tokens of a class.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ClassPolicy {
+    /// Learned and generated like any other word.
+    Learn,
+    /// Never learned, and left out of generated text if learned before.
+    Drop,
+    /// Learned, but generated as the class placeholder.
+    Placeholder,
+}
+
+impl Default for ClassPolicy {
+    fn default() -> Self {
+        DEFAULT_CLASS_POLICY
+    }
+}
+
+/// The policy for every token class.
+#[derive(Clone, Copy, Default)]
+pub struct ClassPolicies {
+    pub urls: ClassPolicy,
+    pub mentions: ClassPolicy,
+    pub hashtags: ClassPolicy,
+    pub commands: ClassPolicy,
+    pub numbers: ClassPolicy,
+}
+
+impl ClassPolicies {
+    pub fn policy(&self, class: Option<TokenClass>) -> ClassPolicy {
+        match class {
+            None => ClassPolicy::Learn,
+            Some(TokenClass::Url) => self.urls,
+            Some(TokenClass::Mention) => self.mentions,
+            Some(TokenClass::Hashtag) => self.hashtags,
+            Some(TokenClass::Command) => self.commands,
+            Some(TokenClass::Number) => self.numbers,
+        }
+    }
+}
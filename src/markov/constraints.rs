@@ -0,0 +1,91 @@
+use super::fingerprint::MAX_SHARED_NGRAM;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_MIN_TOKENS: u64 = 1;
+pub const DEFAULT_MAX_TOKENS: u64 = 0;
+pub const DEFAULT_ENDING: Ending = Ending::Any;
+pub const DEFAULT_RETRIES: u64 = 10;
+pub const DEFAULT_MAX_SHARED_NGRAM: u64 = 0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Ending {
+    Any,
+    Question,
+    Exclamation,
+    Statement,
+}
+
+/// Limits a generated sentence has to satisfy before it is handed out.
+#[derive(Clone, Copy)]
+pub struct Constraints {
+    pub min_tokens: u64,
+    /// 0 means there is no upper bound.
+    pub max_tokens: u64,
+    pub ending: Ending,
+    /// How many extra sentences may be generated when one is rejected.
+    pub retries: u64,
+    /// Longest run of words a sentence may share with any learned line, up to
+    /// `MAX_SHARED_NGRAM`. 0 turns the originality check off. Only lines learned
+    /// while it is set to the current value are fingerprinted, so anything
+    /// learned before it was turned on or changed never counts against a
+    /// sentence.
+    pub max_shared_ngram: u64,
+}
+
+impl Default for Ending {
+    fn default() -> Self {
+        DEFAULT_ENDING
+    }
+}
+
+impl Default for Constraints {
+    fn default() -> Self {
+        Constraints {
+            min_tokens: DEFAULT_MIN_TOKENS,
+            max_tokens: DEFAULT_MAX_TOKENS,
+            ending: DEFAULT_ENDING,
+            retries: DEFAULT_RETRIES,
+            max_shared_ngram: DEFAULT_MAX_SHARED_NGRAM,
+        }
+    }
+}
+
+impl Ending {
+    pub fn accepts(&self, words: &[String]) -> bool {
+        let last = words.last().and_then(|word| word.chars().next_back());
+
+        match self {
+            Ending::Any => true,
+            Ending::Question => last == Some('?'),
+            Ending::Exclamation => last == Some('!'),
+            Ending::Statement => last.is_some() && last != Some('?') && last != Some('!'),
+        }
+    }
+}
+
+impl Constraints {
+    pub fn accepts(&self, words: &[String]) -> bool {
+        let length = words.len() as u64;
+
+        length >= self.min_tokens
+            && (self.max_tokens == 0 || length <= self.max_tokens)
+            && self.ending.accepts(words)
+    }
+
+    /// How many words the runs the originality check looks for are, or `None`
+    /// when it is off.
+    pub fn checked_run(&self) -> Option<usize> {
+        (self.max_shared_ngram != 0).then_some(self.max_shared_ngram as usize + 1)
+    }
+
+    /// Checks for settings the generator can't honour.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_shared_ngram as usize > MAX_SHARED_NGRAM {
+            return Err(format!(
+                "max_shared_ngram can be at most {}, not {}",
+                MAX_SHARED_NGRAM, self.max_shared_ngram
+            ));
+        }
+        Ok(())
+    }
+}
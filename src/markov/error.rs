@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+/// Why a generation was given up on.
+#[derive(Debug)]
+pub enum GenerationError {
+    /// The walk produced more words than the hard token limit allows.
+    TokenLimit(u64),
+    /// The walk kept revisiting the same context window.
+    Cycle,
+    /// The generation took longer than its time limit.
+    Timeout(Duration),
+    /// Every retry produced a sentence that did not fit the constraints.
+    Constraints,
+    /// The phrase to start from was never learned.
+    UnknownPhrase(String),
+    /// The message to reply to had no words in it.
+    EmptyInput,
+}
+
+impl std::fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GenerationError::TokenLimit(limit) => {
+                write!(f, "Generation went over the limit of {} words", limit)
+            }
+            GenerationError::Cycle => write!(f, "Generation got stuck in a loop"),
+            GenerationError::Timeout(timeout) => {
+                write!(f, "Generation timed out after {:.2?}", timeout)
+            }
+            GenerationError::Constraints => {
+                write!(f, "Could not generate a sentence that fits the constraints")
+            }
+            GenerationError::UnknownPhrase(phrase) => {
+                write!(f, "\"{}\" has never been said in this chat", phrase)
+            }
+            GenerationError::EmptyInput => write!(f, "There are no words to reply to"),
+        }
+    }
+}
+
+impl std::error::Error for GenerationError {}
@@ -0,0 +1,34 @@
+/// Longest shared run of words the originality check can be set to allow.
+pub const MAX_SHARED_NGRAM: usize = 7;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// FNV-1a, which unlike `DefaultHasher` is guaranteed to stay the same between
+/// builds, so stored fingerprints keep matching.
+fn hash(words: &[String]) -> u64 {
+    let mut hash = FNV_OFFSET;
+    for word in words {
+        for byte in word.to_lowercase().bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    hash
+}
+
+/// Fingerprints of every run of `length` consecutive words, case-insensitively.
+pub fn fingerprints(words: &[String], length: usize) -> Vec<u64> {
+    if length == 0 || words.len() < length {
+        return vec![];
+    }
+    words.windows(length).map(hash).collect()
+}
+
+/// Hash of a whole learned line. The empty word it starts with keeps it apart
+/// from the fingerprints of runs, as no token is empty.
+pub fn line_hash(words: &[String]) -> u64 {
+    let mut line = vec![String::new()];
+    line.extend_from_slice(words);
+    hash(&line)
+}
@@ -0,0 +1,218 @@
+use super::classes::TokenClass;
+use super::error::GenerationError;
+use super::limits::{Limits, CYCLE_REPEATS};
+use super::{Error, Markov, END_INDEX, MAX_NGRAM_ORDER, START_INDEX};
+
+use futures::stream::{self, Stream};
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+/// A single generated word together with its id in the model.
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub index: u64,
+    pub word: String,
+    pub class: Option<TokenClass>,
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Forward,
+    Backward,
+    Bidirectional,
+}
+
+/// Walks the chain one word at a time and yields the words as a `Stream`.
+///
+/// The stream ends when the walk reaches the start or end of a sentence, or
+/// when the model has nothing to offer for the current context. A walk that
+/// breaks its `Limits` ends with a `GenerationError`.
+pub struct Generator<'a> {
+    markov: &'a Markov,
+    mode: Mode,
+    history: Vec<u64>,
+    future: Vec<u64>,
+    limits: Limits,
+}
+
+struct State<'a> {
+    markov: &'a Markov,
+    mode: Mode,
+    history: Vec<u64>,
+    future: Vec<u64>,
+    pending: VecDeque<u64>,
+    done: bool,
+    limits: Limits,
+    started: Instant,
+    generated: u64,
+    windows: HashMap<Vec<u64>, u64>,
+}
+
+impl<'a> Generator<'a> {
+    /// Generates a whole sentence from its start.
+    pub fn new(markov: &'a Markov) -> Generator<'a> {
+        Generator::forward(markov, vec![START_INDEX; MAX_NGRAM_ORDER])
+    }
+
+    /// Yields the words that follow `context`, in sentence order.
+    pub fn forward(markov: &'a Markov, context: Vec<u64>) -> Generator<'a> {
+        Generator {
+            markov,
+            mode: Mode::Forward,
+            history: context,
+            future: vec![],
+            limits: markov.limits,
+        }
+    }
+
+    /// Yields the words that precede `context`, nearest word first.
+    pub fn backward(markov: &'a Markov, context: Vec<u64>) -> Generator<'a> {
+        Generator {
+            markov,
+            mode: Mode::Backward,
+            history: vec![],
+            future: context,
+            limits: markov.limits,
+        }
+    }
+
+    /// Grows a sentence in both directions around `seed` and yields all of it,
+    /// seed included, in sentence order.
+    pub fn bidirectional(markov: &'a Markov, seed: Vec<u64>) -> Generator<'a> {
+        Generator {
+            markov,
+            mode: Mode::Bidirectional,
+            history: vec![],
+            future: seed,
+            limits: markov.limits,
+        }
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Generator<'a> {
+        self.limits = limits;
+        self
+    }
+
+    pub fn stream(self) -> impl Stream<Item = Result<Token, Error>> + 'a {
+        let state = State {
+            markov: self.markov,
+            mode: self.mode,
+            history: self.history,
+            future: self.future,
+            pending: VecDeque::new(),
+            done: false,
+            limits: self.limits,
+            started: Instant::now(),
+            generated: 0,
+            windows: HashMap::new(),
+        };
+
+        stream::unfold(state, |mut state| async move {
+            if state.done {
+                return None;
+            }
+            // Lets a time limit around the walk fire between words
+            tokio::task::yield_now().await;
+
+            let index = match state.next_index().await {
+                Ok(index) => index?,
+                Err(e) => {
+                    state.done = true;
+                    return Some((Err(e), state));
+                }
+            };
+
+            match state.markov.get_token(index).await {
+                Ok(token) => Some((Ok(token), state)),
+                Err(e) => {
+                    state.done = true;
+                    Some((Err(e), state))
+                }
+            }
+        })
+    }
+}
+
+impl<'a> State<'a> {
+    async fn next_index(&mut self) -> Result<Option<u64>, Error> {
+        match self.mode {
+            Mode::Forward => {
+                if let Some(index) = self.pending.pop_front() {
+                    return Ok(Some(index));
+                }
+
+                let index = match self.markov.next_word(&self.history).await? {
+                    Some(index) if index != END_INDEX => index,
+                    _ => return Ok(None),
+                };
+                self.history.push(index);
+                let window =
+                    self.history[self.history.len().saturating_sub(self.window())..].to_vec();
+                self.check(&window)?;
+                Ok(Some(index))
+            }
+            Mode::Backward => {
+                let index = match self.markov.prev_word(&self.future).await? {
+                    Some(index) if index != START_INDEX => index,
+                    _ => return Ok(None),
+                };
+                self.future.insert(0, index);
+                let window = self.future[..self.window().min(self.future.len())].to_vec();
+                self.check(&window)?;
+                Ok(Some(index))
+            }
+            Mode::Bidirectional => {
+                let mut reached_start = false;
+
+                while let Some(index) = self.markov.prev_word(&self.future).await? {
+                    if index == START_INDEX {
+                        reached_start = true;
+                        break;
+                    }
+                    self.future.insert(0, index);
+                    let window = self.future[..self.window().min(self.future.len())].to_vec();
+                    self.check(&window)?;
+                }
+
+                self.history = if reached_start {
+                    vec![START_INDEX; MAX_NGRAM_ORDER]
+                } else {
+                    vec![]
+                };
+                self.history.extend(&self.future);
+                self.pending.extend(&self.future);
+
+                self.mode = Mode::Forward;
+                Ok(self.pending.pop_front())
+            }
+        }
+    }
+
+    fn window(&self) -> usize {
+        self.limits.cycle_window as usize
+    }
+
+    /// Enforces the limits after every new word, `window` being the most
+    /// recent words of the walk.
+    fn check(&mut self, window: &[u64]) -> Result<(), GenerationError> {
+        self.generated += 1;
+        if self.limits.token_limit != 0 && self.generated > self.limits.token_limit {
+            return Err(GenerationError::TokenLimit(self.limits.token_limit));
+        }
+
+        if let Some(timeout) = self.limits.timeout() {
+            if self.started.elapsed() > timeout {
+                return Err(GenerationError::Timeout(timeout));
+            }
+        }
+
+        if self.limits.cycle_window != 0 && window.len() == self.window() {
+            let count = self.windows.entry(window.to_vec()).or_insert(0);
+            *count += 1;
+            if *count >= CYCLE_REPEATS {
+                return Err(GenerationError::Cycle);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+pub const DEFAULT_TOKEN_LIMIT: u64 = 200;
+pub const DEFAULT_CYCLE_WINDOW: u64 = 4;
+pub const DEFAULT_TIMEOUT_MS: u64 = 5000;
+
+/// A window of words may show up this many times before the walk is a cycle.
+pub const CYCLE_REPEATS: u64 = 3;
+
+/// Hard limits that stop a walk from running away.
+#[derive(Clone, Copy)]
+pub struct Limits {
+    /// Most words a single walk may produce. 0 means no limit.
+    pub token_limit: u64,
+    /// Length of the context window that is watched for repeats. 0 disables it.
+    pub cycle_window: u64,
+    /// 0 means no time limit.
+    pub timeout_ms: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            token_limit: DEFAULT_TOKEN_LIMIT,
+            cycle_window: DEFAULT_CYCLE_WINDOW,
+            timeout_ms: DEFAULT_TIMEOUT_MS,
+        }
+    }
+}
+
+impl Limits {
+    pub fn timeout(&self) -> Option<Duration> {
+        if self.timeout_ms == 0 {
+            None
+        } else {
+            Some(Duration::from_millis(self.timeout_ms))
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use super::classes::TokenClass;
+use super::tokenizer::{is_unspaced, CLOSING, OPENING, SEPARATORS, TERMINAL};
+
+pub const DEFAULT_TRIM_LEADING_PUNCTUATION: bool = false;
+pub const DEFAULT_COLLAPSE_PUNCTUATION: bool = false;
+pub const DEFAULT_BALANCE_BRACKETS: bool = false;
+pub const DEFAULT_TERMINAL_PUNCTUATION: bool = false;
+pub const DEFAULT_CAPITALIZE: bool = false;
+
+/// Brackets and quotes that `balance_brackets` keeps in pairs. `"` is
+/// handled on its own since it opens and closes.
+const PAIRS: [(char, char); 10] = [
+    ('(', ')'),
+    ('[', ']'),
+    ('{', '}'),
+    ('“', '”'),
+    ('«', '»'),
+    ('‹', '›'),
+    ('「', '」'),
+    ('『', '』'),
+    ('（', '）'),
+    ('【', '】'),
+];
+
+/// Cleanup steps for generated text. The enabled ones run in the order the
+/// fields are listed.
+#[derive(Clone, Copy)]
+pub struct PostProcessing {
+    /// Drops punctuation like `,` or `.` that the text starts with.
+    pub trim_leading_punctuation: bool,
+    /// Shortens runs like `!!!` or `,,` to a single mark. Ellipses are kept.
+    pub collapse_punctuation: bool,
+    /// Closes brackets and quotes that were left open and drops closing ones
+    /// that were never opened.
+    pub balance_brackets: bool,
+    /// Ends the text with a full stop if it doesn't end in punctuation already.
+    pub terminal_punctuation: bool,
+    /// Uppercases the first letter, unless the text starts with a link, mention
+    /// or other token that would break.
+    pub capitalize: bool,
+}
+
+impl Default for PostProcessing {
+    fn default() -> Self {
+        PostProcessing {
+            trim_leading_punctuation: DEFAULT_TRIM_LEADING_PUNCTUATION,
+            collapse_punctuation: DEFAULT_COLLAPSE_PUNCTUATION,
+            balance_brackets: DEFAULT_BALANCE_BRACKETS,
+            terminal_punctuation: DEFAULT_TERMINAL_PUNCTUATION,
+            capitalize: DEFAULT_CAPITALIZE,
+        }
+    }
+}
+
+impl PostProcessing {
+    pub fn apply(&self, text: String) -> String {
+        self.apply_part(text, true, true)
+    }
+
+    /// Cleans up generated text that makes up only part of a sentence, the
+    /// rest being the user's. `starts` and `ends` tell whether it begins and
+    /// finishes the sentence. Brackets are only balanced in whole sentences,
+    /// as the other part may open or close them.
+    pub fn apply_part(&self, text: String, starts: bool, ends: bool) -> String {
+        let mut text = text;
+        if self.trim_leading_punctuation && starts {
+            text = trim_leading_punctuation(&text);
+        }
+        if self.collapse_punctuation {
+            text = collapse_punctuation(&text);
+        }
+        if self.balance_brackets && starts && ends {
+            text = balance_brackets(&text);
+        }
+        if self.terminal_punctuation && ends {
+            text = terminal_punctuation(&text);
+        }
+        if self.capitalize && starts {
+            text = capitalize(&text);
+        }
+        text
+    }
+}
+
+/// Text that is nothing but punctuation is left alone rather than emptied.
+fn trim_leading_punctuation(text: &str) -> String {
+    let trimmed = text.trim_start_matches(|c: char| {
+        c.is_whitespace()
+            || TERMINAL.contains(&c)
+            || SEPARATORS.contains(&c)
+            || CLOSING.contains(&c)
+    });
+    if trimmed.is_empty() {
+        text.to_owned()
+    } else {
+        trimmed.to_owned()
+    }
+}
+
+fn collapse_punctuation(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut previous = None;
+    let mut run = 0;
+
+    for c in text.chars() {
+        if previous == Some(c) {
+            run += 1;
+        } else {
+            run = 1;
+        }
+        previous = Some(c);
+
+        let limit = if c == '.' { 3 } else { 1 };
+        if run > limit && (TERMINAL.contains(&c) || SEPARATORS.contains(&c)) {
+            continue;
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Where closing brackets go: before the punctuation that ends the text.
+fn closing_position(text: &str) -> usize {
+    text.trim_end_matches(|c: char| TERMINAL.contains(&c)).len()
+}
+
+/// Brackets right after `:` or `;` are taken for smileys like `:)` or `;(`
+/// and left alone.
+fn balance_brackets(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut open = vec![];
+    let mut previous = None;
+
+    for c in text.chars() {
+        let smiley = matches!(previous, Some(':') | Some(';'));
+        previous = Some(c);
+
+        if smiley && PAIRS.iter().any(|pair| pair.0 == c || pair.1 == c) {
+            result.push(c);
+            continue;
+        }
+
+        if c == '"' {
+            if open.last() == Some(&'"') {
+                open.pop();
+            } else {
+                open.push('"');
+            }
+        } else if let Some(&(opening, _)) = PAIRS.iter().find(|pair| pair.0 == c) {
+            open.push(opening);
+        } else if let Some(&(opening, _)) = PAIRS.iter().find(|pair| pair.1 == c) {
+            if open.last() != Some(&opening) {
+                continue;
+            }
+            open.pop();
+        }
+        result.push(c);
+    }
+
+    let closing: String = open
+        .iter()
+        .rev()
+        .map(|&opening| {
+            PAIRS
+                .iter()
+                .find(|pair| pair.0 == opening)
+                .map_or('"', |pair| pair.1)
+        })
+        .collect();
+    result.insert_str(closing_position(&result), &closing);
+    result
+}
+
+fn terminal_punctuation(text: &str) -> String {
+    let body = text.trim_end_matches(|c: char| {
+        c.is_whitespace() || CLOSING.contains(&c) || c == '"' || c == '\''
+    });
+    let Some(last) = body.chars().next_back() else {
+        return text.to_owned();
+    };
+
+    if SEPARATORS.contains(&last) {
+        let mut result = text.to_owned();
+        result.replace_range(body.len() - last.len_utf8()..body.len(), ".");
+        result
+    } else if last.is_alphanumeric() && !is_unspaced(last) {
+        let mut result = text.trim_end().to_owned();
+        result.push('.');
+        result
+    } else {
+        text.to_owned()
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let start = text.len()
+        - text
+            .trim_start_matches(|c: char| OPENING.contains(&c) || c == '"')
+            .len();
+    let first_word = text[start..].split_whitespace().next().unwrap_or_default();
+    if TokenClass::of(first_word).is_some() {
+        return text.to_owned();
+    }
+
+    let mut chars = text[start..].chars();
+    match chars.next() {
+        Some(c) if c.is_lowercase() => {
+            format!("{}{}{}", &text[..start], c.to_uppercase(), chars.as_str())
+        }
+        _ => text.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trims_leading_punctuation() {
+        assert_eq!(trim_leading_punctuation(", and then"), "and then");
+        assert_eq!(trim_leading_punctuation(") ok"), "ok");
+    }
+
+    #[test]
+    fn keeps_text_that_is_only_punctuation() {
+        assert_eq!(trim_leading_punctuation("..."), "...");
+    }
+
+    #[test]
+    fn collapses_repeated_punctuation() {
+        assert_eq!(collapse_punctuation("what!!! no,, way??"), "what! no, way?");
+        assert_eq!(collapse_punctuation("well...."), "well...");
+    }
+
+    #[test]
+    fn closes_open_brackets_before_the_full_stop() {
+        assert_eq!(balance_brackets("see (this [one."), "see (this [one]).");
+        assert_eq!(balance_brackets("he said \"hi"), "he said \"hi\"");
+    }
+
+    #[test]
+    fn drops_unopened_closing_brackets() {
+        assert_eq!(balance_brackets("oops) here"), "oops here");
+    }
+
+    #[test]
+    fn leaves_smileys_alone() {
+        assert_eq!(balance_brackets("lol :)"), "lol :)");
+        assert_eq!(balance_brackets("sad :( but ok ;)"), "sad :( but ok ;)");
+    }
+
+    #[test]
+    fn ends_with_a_full_stop() {
+        assert_eq!(terminal_punctuation("hello there"), "hello there.");
+        assert_eq!(terminal_punctuation("hello there,"), "hello there.");
+        assert_eq!(terminal_punctuation("(hello there)"), "(hello there).");
+        assert_eq!(terminal_punctuation("really?"), "really?");
+    }
+
+    #[test]
+    fn capitalizes_the_first_letter() {
+        assert_eq!(capitalize("\"hello\" there"), "\"Hello\" there");
+        assert_eq!(capitalize("élan"), "Élan");
+    }
+
+    #[test]
+    fn does_not_capitalize_links_or_mentions() {
+        assert_eq!(capitalize("https://x.com is up"), "https://x.com is up");
+        assert_eq!(capitalize("@alice hi"), "@alice hi");
+    }
+
+    #[test]
+    fn leaves_the_user_side_of_a_partial_sentence_alone() {
+        let processing = PostProcessing {
+            trim_leading_punctuation: true,
+            collapse_punctuation: true,
+            balance_brackets: true,
+            terminal_punctuation: true,
+            capitalize: true,
+        };
+        assert_eq!(
+            processing.apply_part(", and then some".to_owned(), false, true),
+            ", and then some."
+        );
+        assert_eq!(
+            processing.apply_part("so i said".to_owned(), true, false),
+            "So i said"
+        );
+    }
+}
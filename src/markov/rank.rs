@@ -0,0 +1,130 @@
+use super::tokenizer::Tokenizer;
+use super::{Error, Markov};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+pub const DEFAULT_CANDIDATES: u64 = 3;
+pub const DEFAULT_RANKER: RankerType = RankerType::Keywords;
+
+/// Picks the best of several generated candidates. Higher ranks win.
+#[async_trait]
+pub trait Ranker: Send + Sync {
+    /// `input` holds the words of the message being answered, or nothing when
+    /// the sentence is not a reply.
+    async fn rank(&self, markov: &Markov, words: &[String], input: &[String])
+        -> Result<f64, Error>;
+
+    /// Whether the rank depends on `input`, so that ranking is pointless
+    /// without one.
+    fn uses_input(&self) -> bool {
+        false
+    }
+}
+
+/// The built-in rankers, as chosen in a chat's config.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum RankerType {
+    /// Prefers sentences closest to this many words.
+    Length(u64),
+    Novelty,
+    Keywords,
+    Likelihood,
+}
+
+impl Default for RankerType {
+    fn default() -> Self {
+        DEFAULT_RANKER
+    }
+}
+
+impl RankerType {
+    pub fn build(&self) -> Arc<dyn Ranker> {
+        match self {
+            RankerType::Length(target) => Arc::new(LengthRanker { target: *target }),
+            RankerType::Novelty => Arc::new(NoveltyRanker),
+            RankerType::Keywords => Arc::new(KeywordRanker),
+            RankerType::Likelihood => Arc::new(LikelihoodRanker),
+        }
+    }
+}
+
+/// Lowercased words with punctuation left out.
+fn word_set(tokenizer: &dyn Tokenizer, words: &[String]) -> HashSet<String> {
+    words
+        .iter()
+        .filter(|word| !tokenizer.is_punctuation(word))
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+pub struct LengthRanker {
+    pub target: u64,
+}
+
+#[async_trait]
+impl Ranker for LengthRanker {
+    async fn rank(&self, _: &Markov, words: &[String], _: &[String]) -> Result<f64, Error> {
+        Ok(-(words.len() as f64 - self.target as f64).abs())
+    }
+}
+
+/// Prefers sentences that reuse few of the input's words.
+pub struct NoveltyRanker;
+
+#[async_trait]
+impl Ranker for NoveltyRanker {
+    async fn rank(
+        &self,
+        markov: &Markov,
+        words: &[String],
+        input: &[String],
+    ) -> Result<f64, Error> {
+        let words = word_set(markov.tokenizer(), words);
+        if words.is_empty() {
+            return Ok(0.0);
+        }
+
+        let input = word_set(markov.tokenizer(), input);
+        let novel = words.difference(&input).count();
+        Ok(novel as f64 / words.len() as f64)
+    }
+
+    fn uses_input(&self) -> bool {
+        true
+    }
+}
+
+/// Prefers sentences that share words with the input.
+pub struct KeywordRanker;
+
+#[async_trait]
+impl Ranker for KeywordRanker {
+    async fn rank(
+        &self,
+        markov: &Markov,
+        words: &[String],
+        input: &[String],
+    ) -> Result<f64, Error> {
+        let words = word_set(markov.tokenizer(), words);
+        let input = word_set(markov.tokenizer(), input);
+        Ok(words.intersection(&input).count() as f64)
+    }
+
+    fn uses_input(&self) -> bool {
+        true
+    }
+}
+
+/// Prefers sentences the model finds likely, per word so length does not count.
+pub struct LikelihoodRanker;
+
+#[async_trait]
+impl Ranker for LikelihoodRanker {
+    async fn rank(&self, markov: &Markov, words: &[String], _: &[String]) -> Result<f64, Error> {
+        let score = markov.score(&markov.tokenizer().detokenize(words)).await?;
+        Ok(score.log_probability / score.transitions as f64)
+    }
+}
@@ -0,0 +1,154 @@
+pub const DEFAULT_TEMPERATURE: f64 = 1.0;
+pub const DEFAULT_TOP_K: u64 = 0;
+pub const DEFAULT_TOP_P: f64 = 1.0;
+
+/// Reshapes a transition distribution before a word is sampled from it.
+#[derive(Clone, Copy)]
+pub struct Sampling {
+    /// Below 1 favours likely words, above 1 flattens the distribution.
+    /// 0 always picks the most likely word.
+    pub temperature: f64,
+    /// Only the `top_k` most likely words are kept. 0 keeps all of them.
+    pub top_k: u64,
+    /// Only the most likely words that together make up `top_p` of the
+    /// probability are kept. 1 keeps all of them.
+    pub top_p: f64,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Sampling {
+            temperature: DEFAULT_TEMPERATURE,
+            top_k: DEFAULT_TOP_K,
+            top_p: DEFAULT_TOP_P,
+        }
+    }
+}
+
+impl Sampling {
+    pub fn check_temperature(temperature: f64) -> Result<(), String> {
+        if !temperature.is_finite() || temperature < 0.0 {
+            return Err(format!(
+                "Temperature has to be 0 or more, not {}",
+                temperature
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn check_top_p(top_p: f64) -> Result<(), String> {
+        if !(top_p > 0.0 && top_p <= 1.0) {
+            return Err(format!(
+                "Top-p has to be above 0 and at most 1, not {}",
+                top_p
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks for settings that don't describe a distribution.
+    pub fn validate(&self) -> Result<(), String> {
+        Sampling::check_temperature(self.temperature)?;
+        Sampling::check_top_p(self.top_p)
+    }
+
+    pub fn reshape(&self, vec: &[(u64, f64)]) -> Vec<(u64, f64)> {
+        let mut vec: Vec<(u64, f64)> = vec.iter().filter(|item| item.1 > 0.0).copied().collect();
+        if vec.is_empty() {
+            return vec;
+        }
+        vec.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        if self.temperature <= 0.0 {
+            vec.truncate(1);
+            return vec;
+        }
+
+        if self.temperature != 1.0 {
+            let max = vec[0].1.ln();
+            for item in vec.iter_mut() {
+                item.1 = ((item.1.ln() - max) / self.temperature).exp();
+            }
+        }
+
+        if self.top_k != 0 {
+            vec.truncate(self.top_k as usize);
+        }
+
+        if self.top_p > 0.0 && self.top_p < 1.0 {
+            let total: f64 = vec.iter().map(|item| item.1).sum();
+            let mut mass = 0.0;
+            let keep = vec
+                .iter()
+                .take_while(|item| {
+                    let keep = mass < self.top_p * total;
+                    mass += item.1;
+                    keep
+                })
+                .count();
+            vec.truncate(keep.max(1));
+        }
+
+        vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISTRIBUTION: [(u64, f64); 4] = [(1, 0.2), (2, 0.5), (3, 0.1), (4, 0.2)];
+
+    fn sampling(temperature: f64, top_k: u64, top_p: f64) -> Sampling {
+        Sampling {
+            temperature,
+            top_k,
+            top_p,
+        }
+    }
+
+    fn indices(vec: &[(u64, f64)]) -> Vec<u64> {
+        vec.iter().map(|item| item.0).collect()
+    }
+
+    #[test]
+    fn zero_temperature_keeps_only_the_most_likely_word() {
+        let reshaped = sampling(0.0, 0, 1.0).reshape(&DISTRIBUTION);
+        assert_eq!(indices(&reshaped), vec![2]);
+    }
+
+    #[test]
+    fn temperature_keeps_the_order() {
+        for temperature in [0.5, 1.0, 2.0] {
+            let reshaped = sampling(temperature, 0, 1.0).reshape(&DISTRIBUTION);
+            assert_eq!(reshaped.len(), DISTRIBUTION.len());
+            assert_eq!(reshaped[0].0, 2);
+            assert_eq!(reshaped[3].0, 3);
+            assert!(reshaped.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        }
+
+        // Lower temperatures widen the gap between likely and unlikely words
+        let sharp = sampling(0.5, 0, 1.0).reshape(&DISTRIBUTION);
+        let flat = sampling(2.0, 0, 1.0).reshape(&DISTRIBUTION);
+        assert!(sharp[3].1 / sharp[0].1 < flat[3].1 / flat[0].1);
+    }
+
+    #[test]
+    fn top_k_keeps_the_k_most_likely_words() {
+        let reshaped = sampling(1.0, 1, 1.0).reshape(&DISTRIBUTION);
+        assert_eq!(indices(&reshaped), vec![2]);
+        assert_eq!(sampling(1.0, 3, 1.0).reshape(&DISTRIBUTION).len(), 3);
+        assert_eq!(sampling(1.0, 10, 1.0).reshape(&DISTRIBUTION).len(), 4);
+    }
+
+    #[test]
+    fn top_p_keeps_the_smallest_prefix_that_reaches_p() {
+        assert_eq!(sampling(1.0, 0, 0.5).reshape(&DISTRIBUTION).len(), 1);
+        assert_eq!(sampling(1.0, 0, 0.65).reshape(&DISTRIBUTION).len(), 2);
+        assert_eq!(sampling(1.0, 0, 0.85).reshape(&DISTRIBUTION).len(), 3);
+        assert_eq!(sampling(1.0, 0, 0.95).reshape(&DISTRIBUTION).len(), 4);
+        // Even a tiny p keeps the most likely word
+        let reshaped = sampling(1.0, 0, 1e-9).reshape(&DISTRIBUTION);
+        assert_eq!(indices(&reshaped), vec![2]);
+    }
+}
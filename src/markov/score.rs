@@ -0,0 +1,82 @@
+use super::{keyword, Direction, Error, Markov, END_INDEX, MAX_NGRAM_ORDER, START_INDEX};
+
+/// Probability given to a word the model has never seen in that context, so
+/// that one unknown word does not make the whole text impossible.
+const UNSEEN_PROBABILITY: f64 = 1e-6;
+
+/// How likely the model is to produce a text.
+#[derive(Clone, Copy, Debug)]
+pub struct Score {
+    /// Natural logarithm of the probability of the whole text.
+    pub log_probability: f64,
+    /// Geometric mean of the inverse probability per word. Lower is more likely.
+    pub perplexity: f64,
+    /// Number of words predicted, including the end of the sentence.
+    pub transitions: usize,
+    /// How many of those the model had never seen.
+    pub unseen: usize,
+}
+
+impl std::fmt::Display for Score {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Log-probability: {:.2}\nPerplexity: {:.2}\nUnseen: {} of {} words",
+            self.log_probability, self.perplexity, self.unseen, self.transitions
+        )
+    }
+}
+
+impl Markov {
+    /// Scores `text` under the chain's `MarkovType`, word by word from the start
+    /// of the sentence to its end.
+    pub async fn score(&self, text: &str) -> Result<Score, Error> {
+        let split = self.tokenizer.tokenize(text);
+        let length = split.len();
+
+        let mut indices = vec![];
+        for (index, word) in split.iter().enumerate() {
+            let found = self
+                .database
+                .get_index((keyword(index, length), word))
+                .await?;
+            indices.push(found);
+        }
+        indices.push(Some(END_INDEX));
+
+        let mut history = vec![START_INDEX; MAX_NGRAM_ORDER];
+        let mut log_probability = 0.0;
+        let mut unseen = 0;
+
+        for index in &indices {
+            let probability = match index {
+                Some(index) => self
+                    .distribution(Direction::Next, &history)
+                    .await?
+                    .into_iter()
+                    .find(|item| item.0 == *index)
+                    .map(|item| item.1),
+                None => None,
+            };
+
+            match probability {
+                Some(p) if p > 0.0 => log_probability += p.ln(),
+                _ => {
+                    unseen += 1;
+                    log_probability += UNSEEN_PROBABILITY.ln();
+                }
+            }
+
+            // Word ids start at 1, so an unknown word matches no context.
+            history.push(index.unwrap_or(0));
+        }
+
+        let transitions = indices.len();
+        Ok(Score {
+            log_probability,
+            perplexity: (-log_probability / transitions as f64).exp(),
+            transitions,
+            unseen,
+        })
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+
+fn aggregate(vec: &[(u64, f64)]) -> BTreeMap<u64, f64> {
+    let mut map = BTreeMap::new();
+    for (index, occurrences) in vec {
+        *map.entry(*index).or_insert(0.0) += occurrences;
+    }
+    map
+}
+
+/// Turns weights into probabilities.
+pub fn normalize(vec: &[(u64, f64)]) -> Vec<(u64, f64)> {
+    let map = aggregate(vec);
+    let total = map.values().sum::<f64>();
+
+    map.into_iter()
+        .map(|(index, count)| (index, count / total))
+        .collect()
+}
+
+/// The distribution `MarkovType::Hybrid` samples from: a continuation of the
+/// double lookup is kept if it was seen at least `threshold` times, otherwise
+/// the single lookup is sampled instead.
+pub fn hybrid(double: &[(u64, f64)], single: &[(u64, f64)], threshold: u64) -> Vec<(u64, f64)> {
+    let double = aggregate(double);
+    let total = double.values().sum::<f64>();
+
+    let mut distribution: BTreeMap<u64, f64> = BTreeMap::new();
+    let mut fallback = 0.0;
+
+    for (index, count) in double {
+        let probability = count / total;
+        if count < threshold as f64 {
+            fallback += probability;
+        } else {
+            *distribution.entry(index).or_insert(0.0) += probability;
+        }
+    }
+
+    if fallback > 0.0 {
+        for (index, probability) in normalize(single) {
+            *distribution.entry(index).or_insert(0.0) += fallback * probability;
+        }
+    }
+
+    distribution.into_iter().collect()
+}
+
+/// Blends a higher order distribution with a lower order one using interpolated
+/// absolute discounting, the scheme Kneser-Ney smoothing is built on.
+///
+/// Every count in the higher order loses `discount` (from 0 to 1), and the mass
+/// that frees up is handed to the lower order. The more distinct continuations
+/// a context has compared to its total, the more it leans on the lower order.
+pub fn interpolate(higher: &[(u64, f64)], lower: &[(u64, f64)], discount: f64) -> Vec<(u64, f64)> {
+    let higher = aggregate(higher);
+    let lower = aggregate(lower);
+
+    let higher_total = higher.values().sum::<f64>();
+    let lower_total = lower.values().sum::<f64>();
+
+    if higher_total == 0.0 {
+        return lower
+            .into_iter()
+            .map(|(index, count)| (index, count / lower_total))
+            .collect();
+    }
+
+    if lower_total == 0.0 {
+        return higher
+            .into_iter()
+            .map(|(index, count)| (index, count / higher_total))
+            .collect();
+    }
+
+    // Decayed weights can be below the discount, and lose only what they have
+    let freed = higher
+        .values()
+        .map(|count| count.min(discount))
+        .sum::<f64>();
+    let backoff_weight = freed / higher_total;
+
+    let mut distribution: BTreeMap<u64, f64> = BTreeMap::new();
+    for (index, count) in &higher {
+        *distribution.entry(*index).or_insert(0.0) += (*count - discount).max(0.0) / higher_total;
+    }
+    for (index, count) in &lower {
+        *distribution.entry(*index).or_insert(0.0) += backoff_weight * *count / lower_total;
+    }
+
+    distribution.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    fn total(distribution: &[(u64, f64)]) -> f64 {
+        distribution
+            .iter()
+            .map(|(_, probability)| probability)
+            .sum()
+    }
+
+    fn probability(distribution: &[(u64, f64)], index: u64) -> f64 {
+        distribution
+            .iter()
+            .find(|(other, _)| *other == index)
+            .map_or(0.0, |(_, probability)| *probability)
+    }
+
+    #[test]
+    fn interpolated_distribution_sums_to_one() {
+        let higher = [(1, 3.0), (2, 1.0), (2, 1.0), (3, 0.5)];
+        let lower = [(1, 2.0), (4, 5.0), (5, 1.0)];
+
+        for discount in [0.0, 0.25, 0.75, 1.0] {
+            let distribution = interpolate(&higher, &lower, discount);
+            assert!((total(&distribution) - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn discount_moves_mass_to_the_lower_order_by_distinct_continuations() {
+        let lower = [(9, 1.0)];
+        // Both contexts were seen 4 times, one with a single continuation and
+        // one with four
+        let narrow = interpolate(&[(1, 4.0)], &lower, 0.5);
+        let wide = interpolate(&[(1, 1.0), (2, 1.0), (3, 1.0), (4, 1.0)], &lower, 0.5);
+
+        assert!((probability(&narrow, 9) - 0.5 * 1.0 / 4.0).abs() < 1e-9);
+        assert!((probability(&wide, 9) - 0.5 * 4.0 / 4.0).abs() < 1e-9);
+        assert!((probability(&narrow, 1) - 3.5 / 4.0).abs() < 1e-9);
+        assert_eq!(
+            interpolate(&[(1, 4.0)], &lower, 0.0),
+            vec![(1, 1.0), (9, 0.0)]
+        );
+    }
+
+    #[test]
+    fn hybrid_matches_sampling_then_falling_back() {
+        let double = [(1, 12.0), (2, 3.0), (3, 10.0)];
+        let single = [(2, 1.0), (4, 3.0)];
+        let threshold = 10;
+        let distribution = hybrid(&double, &single, threshold);
+        assert!((total(&distribution) - 1.0).abs() < 1e-9);
+
+        // How the chain chose before: sample the double lookup, and sample the
+        // single one instead if the pick was seen too few times
+        let mut rng = StdRng::seed_from_u64(7);
+        let samples = 200_000;
+        let mut counts: BTreeMap<u64, u64> = BTreeMap::new();
+        for _ in 0..samples {
+            let (mut index, count) = *double.choose_weighted(&mut rng, |item| item.1).unwrap();
+            if count < threshold as f64 {
+                index = single.choose_weighted(&mut rng, |item| item.1).unwrap().0;
+            }
+            *counts.entry(index).or_insert(0) += 1;
+        }
+
+        for index in 1..=4 {
+            let sampled = *counts.get(&index).unwrap_or(&0) as f64 / samples as f64;
+            assert!((sampled - probability(&distribution, index)).abs() < 0.01);
+        }
+    }
+}
@@ -39,3 +39,19 @@ pub fn is_punctuation(charresult: Result<char, std::char::ParseCharError>) -> bo
         Err(_) => false,
     }
 }
+
+/// Joins words back into a sentence, attaching punctuation to the word before it.
+pub fn detokenize(words: &[String]) -> String {
+    let mut sentence = String::new();
+
+    for word in words {
+        let is_punc = is_punctuation(word.parse::<char>());
+
+        if !sentence.is_empty() && !is_punc {
+            sentence.push(' ');
+        }
+        sentence.push_str(word);
+    }
+
+    sentence
+}
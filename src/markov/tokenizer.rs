@@ -0,0 +1,414 @@
+use super::classes::TokenClass;
+use super::split;
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Whitespace, as existing models were learned with it and mixing tokenizers
+/// leaves old lines that can no longer be found to be forgotten.
+pub const DEFAULT_TOKENIZER: TokenizerType = TokenizerType::Whitespace;
+
+/// Splits text into the tokens the model learns and puts generated tokens
+/// back together.
+pub trait Tokenizer: Send + Sync {
+    fn tokenize(&self, text: &str) -> Vec<String>;
+
+    fn detokenize(&self, words: &[String]) -> String;
+
+    /// Whether a token is punctuation rather than a word.
+    fn is_punctuation(&self, token: &str) -> bool;
+
+    /// Recognizes URLs, mentions, hashtags, commands and numbers.
+    fn classify(&self, token: &str) -> Option<TokenClass> {
+        TokenClass::of(token)
+    }
+
+    /// Appends already detokenized `second` to `first`, leaving out the space
+    /// if `second` starts with punctuation.
+    fn join(&self, first: &str, second: &str) -> String {
+        match second.chars().next() {
+            None => first.to_owned(),
+            Some(c) if first.is_empty() || self.is_punctuation(&c.to_string()) => {
+                format!("{}{}", first, second)
+            }
+            Some(_) => format!("{} {}", first, second),
+        }
+    }
+}
+
+/// The built-in tokenizers, as chosen in a chat's config.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum TokenizerType {
+    Whitespace,
+    Unicode,
+    Cjk,
+}
+
+impl Default for TokenizerType {
+    fn default() -> Self {
+        DEFAULT_TOKENIZER
+    }
+}
+
+impl TokenizerType {
+    pub fn build(&self) -> Arc<dyn Tokenizer> {
+        match self {
+            TokenizerType::Whitespace => Arc::new(WhitespaceTokenizer),
+            TokenizerType::Unicode => Arc::new(UnicodeTokenizer),
+            TokenizerType::Cjk => Arc::new(CjkTokenizer),
+        }
+    }
+}
+
+/// Splits on whitespace and splits a single trailing `.,?!;:` off each word.
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        split::split_sentence(text)
+    }
+
+    fn detokenize(&self, words: &[String]) -> String {
+        split::detokenize(words)
+    }
+
+    fn is_punctuation(&self, token: &str) -> bool {
+        split::is_punctuation(token.parse::<char>())
+    }
+}
+
+/// Punctuation that opens something and takes no space after it.
+pub(super) const OPENING: [char; 16] = [
+    '(', '[', '{', '“', '‘', '«', '‹', '„', '‚', '¿', '¡', '「', '『', '（', '【', '《',
+];
+
+/// Punctuation that closes something and takes no space before it.
+pub(super) const CLOSING: [char; 12] = [
+    ')', ']', '}', '”', '’', '»', '›', '」', '』', '）', '】', '》',
+];
+
+/// Quotes that open or close depending on whether one is already open.
+const QUOTES: [char; 2] = ['"', '\''];
+
+/// Punctuation that ends a sentence. Runs of these like `...` or `?!` stay
+/// a single token.
+pub(super) const TERMINAL: [char; 9] = ['.', '!', '?', '…', '。', '！', '？', '‼', '⁉'];
+
+/// Punctuation that separates and takes no space before it.
+pub(super) const SEPARATORS: [char; 8] = [',', ';', ':', '、', '，', '；', '：', '·'];
+
+/// Punctuation kept inside a word when it has word characters on both sides,
+/// as in `don't`, `3.14` or `https://example.com`.
+const JOINERS: [char; 5] = ['.', ',', ':', '\'', '’'];
+
+/// Invisible characters that would otherwise make two spellings of the same
+/// word different tokens. The zero width joiner is handled separately since
+/// emoji sequences need it.
+const ZERO_WIDTH: [char; 5] = ['\u{200B}', '\u{200C}', '\u{2060}', '\u{FEFF}', '\u{00AD}'];
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+fn is_punctuation_char(c: char) -> bool {
+    OPENING.contains(&c)
+        || CLOSING.contains(&c)
+        || QUOTES.contains(&c)
+        || TERMINAL.contains(&c)
+        || SEPARATORS.contains(&c)
+}
+
+fn is_emoji(grapheme: &str) -> bool {
+    grapheme.chars().any(|c| {
+        matches!(c as u32,
+            0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2300..=0x23FF | 0x2B00..=0x2BFF | 0xFE0F)
+    })
+}
+
+/// Joins tokens, leaving out the space after opening and before closing
+/// punctuation. `spaced` gets the characters on either side of every other
+/// gap and decides whether it gets a space.
+fn rejoin(words: &[String], spaced: impl Fn(char, char) -> bool) -> String {
+    let mut sentence = String::new();
+    let mut glue = true;
+    let mut open = vec![];
+
+    for word in words {
+        let first = word.chars().next().unwrap_or(' ');
+        let single = word.chars().count() == 1;
+
+        let (space, next_glue) = if single && QUOTES.contains(&first) {
+            if open.last() == Some(&first) {
+                open.pop();
+                (false, false)
+            } else {
+                open.push(first);
+                (true, true)
+            }
+        } else if single && OPENING.contains(&first) {
+            (true, true)
+        } else if !word.is_empty() && word.chars().all(is_punctuation_char) {
+            (false, false)
+        } else {
+            (true, false)
+        };
+
+        if let Some(last) = sentence.chars().next_back() {
+            if space && !glue && spaced(last, first) {
+                sentence.push(' ');
+            }
+        }
+        sentence.push_str(word);
+        glue = next_glue;
+    }
+
+    sentence
+}
+
+fn join_with(first: &str, second: &str, spaced: impl Fn(char, char) -> bool) -> String {
+    let attaches =
+        |c: char| CLOSING.contains(&c) || TERMINAL.contains(&c) || SEPARATORS.contains(&c);
+    match (first.chars().next_back(), second.chars().next()) {
+        (_, None) => first.to_owned(),
+        (None, _) => second.to_owned(),
+        (Some(last), Some(c)) if OPENING.contains(&last) || attaches(c) || !spaced(last, c) => {
+            format!("{}{}", first, second)
+        }
+        _ => format!("{} {}", first, second),
+    }
+}
+
+/// Splits on whitespace and then splits emoji, quotes, brackets and other
+/// punctuation, including non-ASCII punctuation, into tokens of their own.
+/// Text is NFC normalized and zero width characters are dropped first.
+pub struct UnicodeTokenizer;
+
+impl UnicodeTokenizer {
+    fn split_chunk(chunk: &str, tokens: &mut Vec<String>) {
+        // Links would be torn apart at their `?`, so only punctuation around
+        // them is split off
+        if chunk.contains("://") {
+            let start = chunk.trim_start_matches(is_punctuation_char);
+            let link = start.trim_end_matches(is_punctuation_char);
+            if !link.is_empty() {
+                Self::split_chunk(&chunk[..chunk.len() - start.len()], tokens);
+                tokens.push(link.to_owned());
+                Self::split_chunk(&start[link.len()..], tokens);
+                return;
+            }
+        }
+
+        let graphemes: Vec<&str> = chunk.graphemes(true).collect();
+        let mut word = String::new();
+        let mut run = String::new();
+
+        let flush = |buffer: &mut String, tokens: &mut Vec<String>| {
+            if !buffer.is_empty() {
+                tokens.push(std::mem::take(buffer));
+            }
+        };
+        let is_word = |grapheme: Option<&&str>| match grapheme {
+            Some(grapheme) => {
+                !is_emoji(grapheme)
+                    && grapheme
+                        .chars()
+                        .next()
+                        .is_some_and(|c| !is_punctuation_char(c))
+            }
+            None => false,
+        };
+
+        for (index, grapheme) in graphemes.iter().enumerate() {
+            if is_emoji(grapheme) {
+                flush(&mut word, tokens);
+                flush(&mut run, tokens);
+                tokens.push(grapheme.to_string());
+                continue;
+            }
+
+            let grapheme = grapheme.replace(ZERO_WIDTH_JOINER, "");
+            let Some(c) = grapheme.chars().next() else {
+                continue;
+            };
+
+            if !is_punctuation_char(c) {
+                flush(&mut run, tokens);
+                word.push_str(&grapheme);
+            } else if JOINERS.contains(&c) && !word.is_empty() && is_word(graphemes.get(index + 1))
+            {
+                word.push_str(&grapheme);
+            } else if TERMINAL.contains(&c) {
+                flush(&mut word, tokens);
+                run.push_str(&grapheme);
+            } else {
+                flush(&mut word, tokens);
+                flush(&mut run, tokens);
+                tokens.push(grapheme);
+            }
+        }
+
+        flush(&mut word, tokens);
+        flush(&mut run, tokens);
+    }
+}
+
+impl Tokenizer for UnicodeTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let text: String = text.nfc().filter(|c| !ZERO_WIDTH.contains(c)).collect();
+        let mut tokens = vec![];
+        for chunk in text.split_whitespace() {
+            Self::split_chunk(chunk, &mut tokens);
+        }
+        tokens
+    }
+
+    fn detokenize(&self, words: &[String]) -> String {
+        rejoin(words, |_, _| true)
+    }
+
+    fn is_punctuation(&self, token: &str) -> bool {
+        !token.is_empty() && token.chars().all(is_punctuation_char)
+    }
+
+    fn join(&self, first: &str, second: &str) -> String {
+        join_with(first, second, |_, _| true)
+    }
+}
+
+/// Whether a character belongs to a script written without spaces between
+/// words, or is punctuation used alongside one.
+pub(super) fn is_unspaced(c: char) -> bool {
+    matches!(c as u32,
+        0x0E00..=0x0EFF // Thai, Lao
+        | 0x1000..=0x109F // Myanmar
+        | 0x1780..=0x17FF // Khmer
+        | 0x3000..=0x30FF // CJK punctuation, Hiragana, Katakana
+        | 0x31F0..=0x31FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xF900..=0xFAFF
+        | 0xFF00..=0xFF9F // Fullwidth punctuation, halfwidth Katakana
+        | 0x20000..=0x2FA1F)
+}
+
+/// Tokenizes like `UnicodeTokenizer`, but also cuts text in scripts that
+/// don't use spaces, like Chinese, Japanese and Thai, into single characters
+/// so the model learns character n-grams. Those characters are joined back
+/// without spaces.
+pub struct CjkTokenizer;
+
+impl Tokenizer for CjkTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let mut tokens = vec![];
+        for token in UnicodeTokenizer.tokenize(text) {
+            if !token.chars().any(is_unspaced) || token.contains("://") {
+                tokens.push(token);
+                continue;
+            }
+
+            let mut rest = String::new();
+            for grapheme in token.graphemes(true) {
+                if grapheme.chars().next().is_some_and(is_unspaced) {
+                    if !rest.is_empty() {
+                        tokens.push(std::mem::take(&mut rest));
+                    }
+                    tokens.push(grapheme.to_owned());
+                } else {
+                    rest.push_str(grapheme);
+                }
+            }
+            if !rest.is_empty() {
+                tokens.push(rest);
+            }
+        }
+        tokens
+    }
+
+    fn detokenize(&self, words: &[String]) -> String {
+        rejoin(words, |last, first| {
+            !(is_unspaced(last) && is_unspaced(first))
+        })
+    }
+
+    fn is_punctuation(&self, token: &str) -> bool {
+        UnicodeTokenizer.is_punctuation(token)
+    }
+
+    fn join(&self, first: &str, second: &str) -> String {
+        join_with(first, second, |last, first| {
+            !(is_unspaced(last) && is_unspaced(first))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(tokenizer: &dyn Tokenizer, text: &str, tokens: &[&str]) {
+        let tokenized = tokenizer.tokenize(text);
+        assert_eq!(tokenized, tokens);
+        assert_eq!(tokenizer.detokenize(&tokenized), text);
+    }
+
+    #[test]
+    fn splits_off_quotes() {
+        round_trip(
+            &UnicodeTokenizer,
+            "\"hello\" there",
+            &["\"", "hello", "\"", "there"],
+        );
+    }
+
+    #[test]
+    fn splits_off_brackets() {
+        round_trip(&UnicodeTokenizer, "(see) this", &["(", "see", ")", "this"]);
+    }
+
+    #[test]
+    fn keeps_ellipses_whole() {
+        round_trip(&UnicodeTokenizer, "wait... what", &["wait", "...", "what"]);
+    }
+
+    #[test]
+    fn splits_off_inverted_punctuation() {
+        round_trip(&UnicodeTokenizer, "¿qué pasa?", &["¿", "qué", "pasa", "?"]);
+    }
+
+    #[test]
+    fn splits_off_ideographic_punctuation() {
+        round_trip(&CjkTokenizer, "你好。世界", &["你", "好", "。", "世", "界"]);
+    }
+
+    #[test]
+    fn keeps_emoji_with_modifiers_whole() {
+        round_trip(&UnicodeTokenizer, "nice 👍🏽 one", &["nice", "👍🏽", "one"]);
+    }
+
+    #[test]
+    fn drops_zero_width_spaces() {
+        assert_eq!(
+            UnicodeTokenizer.tokenize("zero\u{200B}width"),
+            ["zerowidth"]
+        );
+    }
+
+    #[test]
+    fn normalizes_to_nfc() {
+        assert_eq!(UnicodeTokenizer.tokenize("cafe\u{301}"), ["caf\u{e9}"]);
+    }
+
+    #[test]
+    fn keeps_apostrophes_and_hyphens_inside_words() {
+        round_trip(&UnicodeTokenizer, "don't stop-me", &["don't", "stop-me"]);
+    }
+
+    #[test]
+    fn keeps_links_whole() {
+        round_trip(
+            &UnicodeTokenizer,
+            "see https://x.com/a?b=1, ok",
+            &["see", "https://x.com/a?b=1", ",", "ok"],
+        );
+    }
+}
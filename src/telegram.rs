@@ -1,27 +1,61 @@
 use super::database::SqliteDB;
-use super::markov::Markov;
+use super::markov::error::GenerationError;
+use super::markov::{Markov, MarkovBuilder, ANONYMOUS_USER};
 
 use std::sync::Arc;
+use std::time::Duration;
 use teloxide::dispatching::{dialogue, UpdateHandler};
 use teloxide::prelude::*;
+use teloxide::types::MessageEntityKind;
 use teloxide::utils::command::BotCommands;
 use teloxide_macros::BotCommands;
 
 pub mod chat;
 pub mod config;
+pub mod mentions;
+pub mod options;
 
-use chat::{get_user_level, match_user_levels};
+use chat::{get_user_level, match_user_levels, Access};
 use config::MarkovConfig;
+use options::GenerateOptions;
+
+/// How often decayed weights are folded in, faded n-grams pruned and data past
+/// the retention period purged.
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
 
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "lowercase")]
 enum Command {
     #[command(description = "Display this text")]
     Help,
-    #[command(description = "Generate a sentence")]
-    Markov,
+    #[command(
+        description = "Generate a sentence. Options: seed=<n> min=<n> max=<n> temp=<x> topk=<n> topp=<x> end=ask/exclaim/state"
+    )]
+    Markov(String),
+    #[command(
+        description = "Generate a sentence from one user's messages. Use as a reply or with @username"
+    )]
+    Impersonate(String),
     #[command(description = "Generate a reply sentence without appending")]
     Reply(String),
+    #[command(description = "Finish the given sentence")]
+    Continue(String),
+    #[command(description = "Make up a beginning for the given sentence")]
+    Precede(String),
+    #[command(description = "Show how likely the model is to say the given text")]
+    Score(String),
+    #[command(description = "Stop learning from your messages")]
+    OptOut,
+    #[command(description = "Start learning from your messages again")]
+    OptIn,
+    #[command(
+        description = "Remove everything learned from you. Admins can use it as a reply on another user"
+    )]
+    Erase,
+    #[command(description = "Remove the replied message from the model")]
+    Forget,
+    #[command(description = "Show how long messages are kept and when they were last purged")]
+    Retention,
     #[command(description = "Blacklist a user")]
     Blacklist,
     #[command(description = "Unblacklist a user")]
@@ -64,22 +98,115 @@ async fn connect_database(
     Ok(database)
 }
 
-async fn create_markov(
+fn user_database_path(chat_id: &str, user_id: UserId) -> String {
+    format!("./{}/users/{}.db", chat_id, user_id)
+}
+
+/// The model of a single user's messages, kept next to the chat's.
+async fn connect_user_database(
+    chat_id: &str,
+    user_id: UserId,
+) -> Result<SqliteDB, Box<dyn std::error::Error + Send + Sync>> {
+    let dir_name = format!("./{}/users/", chat_id);
+    let path_name = user_database_path(chat_id, user_id);
+    let path = std::path::Path::new(&path_name);
+    let dir = std::path::Path::new(&dir_name);
+
+    std::fs::create_dir_all(dir)?;
+    let database = SqliteDB::new(path).await?;
+
+    Ok(database)
+}
+
+async fn markov_builder(
     chat_id: &str,
     config: &MarkovConfig,
-) -> Result<Markov, Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<MarkovBuilder, Box<dyn std::error::Error + Send + Sync>> {
     let database = Arc::new(connect_database(chat_id).await?);
+    Ok(configure(Markov::builder(database), config))
+}
+
+async fn user_markov_builder(
+    chat_id: &str,
+    user_id: UserId,
+    config: &MarkovConfig,
+) -> Result<MarkovBuilder, Box<dyn std::error::Error + Send + Sync>> {
+    let database = Arc::new(connect_user_database(chat_id, user_id).await?);
+    Ok(configure(Markov::builder(database), config))
+}
 
-    Markov::builder(database)
+/// Sets up `builder` the way `config` says a chat's model behaves. Anything
+/// that generates for a chat, in the bot or not, goes through here.
+pub fn configure(builder: MarkovBuilder, config: &MarkovConfig) -> MarkovBuilder {
+    builder
         .markov_type(config.markov_type)
         .markov_chance(config.chance)
         .reply_mode(config.reply_mode)
-        .build()
-        .await
+        .constraints(config.constraints())
+        .limits(config.limits())
+        .sampling(config.sampling())
+        .half_life_days(config.half_life_days)
+        .candidates(config.candidates)
+        .ranker(config.ranker.build())
+        .tokenizer(config.tokenizer.build())
+        .classes(config.classes())
+        .post_processing(config.post_processing())
+}
+
+async fn create_markov(
+    chat_id: &str,
+    config: &MarkovConfig,
+) -> Result<Markov, Box<dyn std::error::Error + Send + Sync>> {
+    markov_builder(chat_id, config).await?.build().await
 }
 
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
+/// Whether the sender may use a command that needs `access`. They are told
+/// so if they may not.
+async fn has_access(
+    bot: &Bot,
+    msg: &Message,
+    access: Access,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let from = bot
+        .get_chat_member(
+            msg.chat.id,
+            msg.from().expect("Must be MessageKind::Common").id,
+        )
+        .await?;
+    if get_user_level(from, msg.chat.id)
+        .await?
+        .is_authorized(access)
+    {
+        return Ok(true);
+    }
+
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "You do not have permission to use this command! (Access level: {})",
+            access
+        ),
+    )
+    .reply_to_message_id(msg.id)
+    .await?;
+    Ok(false)
+}
+
+/// Gets generated text ready to be sent to a chat, or `None` if nothing is
+/// left to send.
+async fn outgoing(
+    bot: &Bot,
+    chat: ChatId,
+    text: String,
+    config: &MarkovConfig,
+) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let text = mentions::apply(bot, chat, text, config.mentions).await?;
+    // Telegram refuses empty messages
+    Ok((!text.trim().is_empty()).then_some(text))
+}
+
 async fn listen(bot: Bot, msg: Message) -> HandlerResult {
     //let chat_id = msg.chat.id.0.to_string();
     //let database = connect_database(&chat_id).await?;
@@ -99,15 +226,25 @@ async fn listen(bot: Bot, msg: Message) -> HandlerResult {
 
     let bot_id = get_bot_id().await?;
     if let Some(text) = msg.text() {
-        if user_level.is_authorized(config.access.markov.append) {
-            if config.separate_newline {
-                if let Err(e) = markov.append_newlines(text).await {
-                    eprintln!("Couldn't append to database: {}", e);
-                    return Err(e);
-                }
-            } else {
-                if let Err(e) = markov.append_line(text).await {
-                    eprintln!("Couldn't append to database: {}", e);
+        let from = msg.from().expect("Must be MessageKind::Common");
+        if user_level.is_authorized(config.access.markov.append)
+            && !chat::is_opted_out(from.id, msg.chat.id).await?
+        {
+            let author = recorded_as(&config, from.id);
+            if let Err(e) = append(&markov, text, &config, author).await {
+                eprintln!("Couldn't append to database: {}", e);
+                return Err(e);
+            }
+
+            if config.attribution {
+                chat::remember(from, msg.chat.id).await?;
+
+                let user_markov = user_markov_builder(chat_id, from.id, &config)
+                    .await?
+                    .build()
+                    .await?;
+                if let Err(e) = append(&user_markov, text, &config, None).await {
+                    eprintln!("Couldn't append to user database: {}", e);
                     return Err(e);
                 }
             }
@@ -115,15 +252,27 @@ async fn listen(bot: Bot, msg: Message) -> HandlerResult {
     }
 
     if let Some(reply) = msg.reply_to_message() {
-        if reply.from().unwrap().id.to_string() == bot_id {
-            if user_level.is_authorized(config.access.markov.reply) {
-                if let Some(text) = msg.text() {
-                    let sentence = markov.generate_reply(text).await?;
-                    bot.send_message(msg.chat.id, sentence)
-                        .reply_to_message_id(msg.id)
-                        .await?;
-                    return Ok(());
+        if reply.from().unwrap().id.to_string() == bot_id
+            && user_level.is_authorized(config.access.markov.reply)
+        {
+            if let Some(text) = msg.text() {
+                match markov.generate_reply(text).await {
+                    Ok(sentence) => {
+                        if let Some(sentence) =
+                            outgoing(&bot, msg.chat.id, sentence, &config).await?
+                        {
+                            bot.send_message(msg.chat.id, sentence)
+                                .reply_to_message_id(msg.id)
+                                .await?;
+                        }
+                    }
+                    // Nobody asked for this one, so a failed generation is not worth a message
+                    Err(e) if e.is::<GenerationError>() => {
+                        eprintln!("Couldn't generate a reply: {}", e);
+                    }
+                    Err(e) => return Err(e),
                 }
+                return Ok(());
             }
         }
     }
@@ -132,8 +281,17 @@ async fn listen(bot: Bot, msg: Message) -> HandlerResult {
     }
 
     if markov.chance() {
-        let sentence = markov.generate().await?;
-        bot.send_message(msg.chat.id, sentence).await?;
+        match markov.generate().await {
+            Ok(sentence) => {
+                if let Some(sentence) = outgoing(&bot, msg.chat.id, sentence, &config).await? {
+                    bot.send_message(msg.chat.id, sentence).await?;
+                }
+            }
+            Err(e) if e.is::<GenerationError>() => {
+                eprintln!("Couldn't generate a message: {}", e);
+            }
+            Err(e) => return Err(e),
+        }
     }
 
     Ok(())
@@ -146,10 +304,66 @@ async fn help(bot: Bot, msg: Message) -> HandlerResult {
     Ok(())
 }
 
-async fn generate(bot: Bot, msg: Message) -> HandlerResult {
+/// Who a line `user` wrote is recorded as written by, if anyone. Without
+/// attribution lines are still recorded anonymously while a retention period
+/// is set, so the purge can take them back exactly.
+fn recorded_as(config: &MarkovConfig, user: UserId) -> Option<UserId> {
+    if config.attribution {
+        Some(user)
+    } else if config.retention_days != 0 {
+        Some(UserId(ANONYMOUS_USER))
+    } else {
+        None
+    }
+}
+
+/// Learns `text`, line by line if the chat separates newlines. Lines are
+/// attributed to `user` when given, so they can be erased later.
+async fn append(
+    markov: &Markov,
+    text: &str,
+    config: &MarkovConfig,
+    user: Option<UserId>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let lines: Vec<&str> = if config.separate_newline {
+        text.split('\n').map(str::trim).collect()
+    } else {
+        vec![text]
+    };
+
+    for line in lines.into_iter().filter(|line| !line.is_empty()) {
+        match user {
+            Some(UserId(user_id)) => markov.append_attributed(line, user_id).await?,
+            None => markov.append_line(line).await?,
+        }
+    }
+    Ok(())
+}
+
+/// Takes back what `append` learned from `text`, which `user` wrote. Returns
+/// true if any line was removed.
+async fn remove(
+    markov: &Markov,
+    text: &str,
+    config: &MarkovConfig,
+    user: UserId,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let lines: Vec<&str> = if config.separate_newline {
+        text.split('\n').map(str::trim).collect()
+    } else {
+        vec![text]
+    };
+
+    let mut removed = false;
+    for line in lines.into_iter().filter(|line| !line.is_empty()) {
+        removed |= markov.remove_attributed(line, user.0).await?;
+    }
+    Ok(removed)
+}
+
+async fn generate(bot: Bot, msg: Message, cmd: Command) -> HandlerResult {
     let chat_id = &msg.chat.id.to_string();
     let config = config::get_config(chat_id).await?;
-    let markov = create_markov(chat_id, &config).await?;
 
     let from = bot
         .get_chat_member(
@@ -173,11 +387,168 @@ async fn generate(bot: Bot, msg: Message) -> HandlerResult {
         return Ok(());
     }
 
-    let sentence = markov.generate().await;
+    let options = match cmd {
+        Command::Markov(text) => GenerateOptions::parse(&text),
+        _ => Ok(GenerateOptions::default()),
+    };
+    let options = match options {
+        Ok(options) => options,
+        Err(e) => {
+            bot.send_message(msg.chat.id, e)
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    if let Some(replied) = msg.reply_to_message().and_then(|replied| replied.from()) {
+        if config.attribution && !replied.is_bot {
+            return impersonate_user(bot, &msg, &config, replied.id, options).await;
+        }
+    }
+
+    let markov = options
+        .apply(markov_builder(chat_id, &config).await?, &config)
+        .build()
+        .await?;
+    let sentence = if options.phrase.is_empty() {
+        markov.generate().await
+    } else {
+        markov.generate_from(&options.phrase).await
+    };
+
+    send_generated(bot, msg, sentence, &config).await
+}
+
+async fn impersonate(bot: Bot, msg: Message, cmd: Command) -> HandlerResult {
+    let chat_id = &msg.chat.id.to_string();
+    let config = config::get_config(chat_id).await?;
+
+    if !has_access(&bot, &msg, config.access.markov.generate).await? {
+        return Ok(());
+    }
+
+    if !config.attribution {
+        bot.send_message(msg.chat.id, "This chat doesn't keep track of who said what")
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    let text = match cmd {
+        Command::Impersonate(text) => text,
+        _ => String::new(),
+    };
+    let (target, text) = match target_user(&msg, &text).await? {
+        Some(found) => found,
+        None => {
+            bot.send_message(
+                msg.chat.id,
+                "Use this command as a reply on another user, or mention someone I have learned from",
+            )
+            .reply_to_message_id(msg.id)
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let options = match GenerateOptions::parse(&text) {
+        Ok(options) => options,
+        Err(e) => {
+            bot.send_message(msg.chat.id, e)
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    impersonate_user(bot, &msg, &config, target, options).await
+}
+
+/// The user a command is about: the author of the replied message, or the
+/// first user mentioned in `text`, together with the rest of `text`.
+async fn target_user(
+    msg: &Message,
+    text: &str,
+) -> Result<Option<(UserId, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(user) = msg.reply_to_message().and_then(|replied| replied.from()) {
+        return Ok(Some((user.id, text.to_string())));
+    }
+
+    for entity in msg.parse_entities().unwrap_or_default() {
+        let target = match entity.kind() {
+            MessageEntityKind::TextMention { user } => Some(user.id),
+            MessageEntityKind::Mention => chat::find_member(entity.text(), msg.chat.id).await?,
+            _ => continue,
+        };
+        return Ok(target.map(|target| (target, text.replacen(entity.text(), "", 1))));
+    }
+    Ok(None)
+}
+
+/// Generates from `user`'s own messages, or from the whole chat if they
+/// haven't written enough yet.
+async fn impersonate_user(
+    bot: Bot,
+    msg: &Message,
+    config: &MarkovConfig,
+    user: UserId,
+    options: GenerateOptions,
+) -> HandlerResult {
+    let chat_id = &msg.chat.id.to_string();
+
+    // Checked by id, as the user may have left the chat since
+    if chat::is_blacklisted(user, msg.chat.id).await? {
+        bot.send_message(msg.chat.id, "That user is blacklisted!")
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
+
+    if chat::is_opted_out(user, msg.chat.id).await? {
+        bot.send_message(msg.chat.id, "That user has opted out of being learned from")
+            .reply_to_message_id(msg.id)
+            .await?;
+        return Ok(());
+    }
 
+    let mut markov = options
+        .apply(user_markov_builder(chat_id, user, config).await?, config)
+        .build()
+        .await?;
+    if markov.lines().await? < config.min_user_lines as f64 {
+        markov = options
+            .apply(markov_builder(chat_id, config).await?, config)
+            .build()
+            .await?;
+    }
+
+    let sentence = if options.phrase.is_empty() {
+        markov.generate().await
+    } else {
+        markov.generate_from(&options.phrase).await
+    };
+
+    send_generated(bot, msg.clone(), sentence, config).await
+}
+
+async fn send_generated(
+    bot: Bot,
+    msg: Message,
+    sentence: Result<String, Box<dyn std::error::Error + Send + Sync>>,
+    config: &MarkovConfig,
+) -> HandlerResult {
     match sentence {
         Ok(text) => {
-            bot.send_message(msg.chat.id, text).await?;
+            if let Some(text) = outgoing(&bot, msg.chat.id, text, config).await? {
+                bot.send_message(msg.chat.id, text).await?;
+            }
+            Ok(())
+        }
+        Err(e) if e.is::<GenerationError>() => {
+            bot.send_message(msg.chat.id, e.to_string())
+                .reply_to_message_id(msg.id)
+                .await?;
             Ok(())
         }
         Err(e) => {
@@ -189,7 +560,7 @@ async fn generate(bot: Bot, msg: Message) -> HandlerResult {
 
 async fn reply(bot: Bot, msg: Message, cmd: Command) -> HandlerResult {
     let chat_id = &msg.chat.id.to_string();
-    let config = config::get_config(&chat_id).await?;
+    let config = config::get_config(chat_id).await?;
 
     let from = bot
         .get_chat_member(
@@ -222,7 +593,9 @@ async fn reply(bot: Bot, msg: Message, cmd: Command) -> HandlerResult {
 
             match sentence {
                 Ok(text) => {
-                    bot.send_message(msg.chat.id, text).await?;
+                    if let Some(text) = outgoing(&bot, msg.chat.id, text, &config).await? {
+                        bot.send_message(msg.chat.id, text).await?;
+                    }
                 }
                 Err(e) => {
                     eprintln!("{}", e);
@@ -239,9 +612,233 @@ async fn reply(bot: Bot, msg: Message, cmd: Command) -> HandlerResult {
     Ok(())
 }
 
+async fn continue_sentence(bot: Bot, msg: Message, cmd: Command) -> HandlerResult {
+    let chat_id = &msg.chat.id.to_string();
+    let config = config::get_config(chat_id).await?;
+
+    if !has_access(&bot, &msg, config.access.markov.generate).await? {
+        return Ok(());
+    }
+
+    if let Command::Continue(text) = cmd {
+        if text.trim().is_empty() {
+            bot.send_message(msg.chat.id, "The command was empty!")
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+
+        let markov = create_markov(chat_id, &config).await?;
+        let sentence = markov.continue_from(text.trim()).await;
+        return send_generated(bot, msg, sentence, &config).await;
+    }
+
+    Ok(())
+}
+
+async fn precede(bot: Bot, msg: Message, cmd: Command) -> HandlerResult {
+    let chat_id = &msg.chat.id.to_string();
+    let config = config::get_config(chat_id).await?;
+
+    if !has_access(&bot, &msg, config.access.markov.generate).await? {
+        return Ok(());
+    }
+
+    if let Command::Precede(text) = cmd {
+        if text.trim().is_empty() {
+            bot.send_message(msg.chat.id, "The command was empty!")
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+
+        let markov = create_markov(chat_id, &config).await?;
+        let sentence = markov.precede(text.trim()).await;
+        return send_generated(bot, msg, sentence, &config).await;
+    }
+
+    Ok(())
+}
+
+async fn score(bot: Bot, msg: Message, cmd: Command) -> HandlerResult {
+    let chat_id = &msg.chat.id.to_string();
+    let config = config::get_config(chat_id).await?;
+
+    if !has_access(&bot, &msg, config.access.markov.generate).await? {
+        return Ok(());
+    }
+
+    if let Command::Score(text) = cmd {
+        if text.trim().is_empty() {
+            bot.send_message(msg.chat.id, "The command was empty!")
+                .reply_to_message_id(msg.id)
+                .await?;
+            return Ok(());
+        }
+
+        let markov = create_markov(chat_id, &config).await?;
+        let score = markov.score(&text).await?;
+        bot.send_message(msg.chat.id, score.to_string())
+            .reply_to_message_id(msg.id)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn opt_out(bot: Bot, msg: Message) -> HandlerResult {
+    let config = config::get_config(&msg.chat.id.to_string()).await?;
+    let user = msg.from().expect("Must be MessageKind::Common").id;
+    chat::opt_out(user, msg.chat.id).await?;
+
+    // Without attribution there's nothing /erase could take back
+    let text = if config.attribution {
+        "I won't learn from your messages anymore. Use /erase to remove what I already learned"
+    } else {
+        "I won't learn from your messages anymore"
+    };
+    bot.send_message(msg.chat.id, text)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+async fn opt_in(bot: Bot, msg: Message) -> HandlerResult {
+    let user = msg.from().expect("Must be MessageKind::Common").id;
+    chat::opt_in(user, msg.chat.id).await?;
+
+    bot.send_message(msg.chat.id, "I will learn from your messages again")
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+async fn erase(bot: Bot, msg: Message) -> HandlerResult {
+    let chat_id = &msg.chat.id.to_string();
+    let config = config::get_config(chat_id).await?;
+    let from = msg.from().expect("Must be MessageKind::Common").id;
+
+    let user = match msg.reply_to_message().and_then(|replied| replied.from()) {
+        Some(replied) if replied.id != from => {
+            let user_level =
+                get_user_level(bot.get_chat_member(msg.chat.id, from).await?, msg.chat.id).await?;
+            if !user_level.is_authorized(config.access.admin_commands.forget) {
+                bot.send_message(
+                    msg.chat.id,
+                    format!(
+                        "You do not have permission to erase other users! (Access level: {})",
+                        config.access.admin_commands.forget
+                    ),
+                )
+                .reply_to_message_id(msg.id)
+                .await?;
+                return Ok(());
+            }
+            replied.id
+        }
+        _ => from,
+    };
+
+    let markov = create_markov(chat_id, &config).await?;
+    let removed = markov.erase_user(user.0).await?;
+
+    let user_model = user_database_path(chat_id, user);
+    if std::path::Path::new(&user_model).exists() {
+        std::fs::remove_file(user_model)?;
+    }
+
+    // Lines learned without attribution can't be told apart, so only those
+    // from while it was on can be erased
+    let text = match (config.attribution, removed) {
+        (true, _) => format!("Erased {} learned lines", removed),
+        (false, 0) => String::from(
+            "This chat doesn't keep track of who said what, so I can't tell which lines to erase",
+        ),
+        (false, _) => format!(
+            "Erased {} learned lines from when this chat kept track of who said what. \
+            Lines learned since can't be erased",
+            removed
+        ),
+    };
+    bot.send_message(msg.chat.id, text)
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
+async fn forget(bot: Bot, msg: Message) -> HandlerResult {
+    let chat_id = &msg.chat.id.to_string();
+    let config = config::get_config(chat_id).await?;
+
+    if !has_access(&bot, &msg, config.access.admin_commands.forget).await? {
+        return Ok(());
+    }
+
+    let replied = msg.reply_to_message();
+    match replied.and_then(|replied| Some((replied.from()?.id, replied.text()?))) {
+        Some((author, text)) => {
+            let markov = create_markov(chat_id, &config).await?;
+            let recorded = recorded_as(&config, author).unwrap_or(author);
+            let removed = remove(&markov, text, &config, recorded).await?;
+
+            if std::path::Path::new(&user_database_path(chat_id, author)).exists() {
+                let user_markov = user_markov_builder(chat_id, author, &config)
+                    .await?
+                    .build()
+                    .await?;
+                remove(&user_markov, text, &config, author).await?;
+            }
+
+            let response = if removed {
+                "Message has been forgotten"
+            } else {
+                "That message was never learned"
+            };
+            bot.send_message(msg.chat.id, response)
+                .reply_to_message_id(msg.id)
+                .await?;
+        }
+        None => {
+            bot.send_message(msg.chat.id, "Use this command as a reply on a text message")
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn retention(bot: Bot, msg: Message) -> HandlerResult {
+    let chat_id = &msg.chat.id.to_string();
+    let config = config::get_config(chat_id).await?;
+
+    if !has_access(&bot, &msg, config.access.admin_commands.config).await? {
+        return Ok(());
+    }
+
+    let kept = match config.retention_days {
+        0 => String::from("Messages are kept forever"),
+        days => format!(
+            "Messages are kept for {} days. Anything learned before that was set is only \
+            forgotten where nothing said since repeats it",
+            days
+        ),
+    };
+    let last_purge = match create_markov(chat_id, &config).await?.last_purge().await? {
+        Some(time) => {
+            let elapsed = time.elapsed().unwrap_or_default().as_secs();
+            format!("Last purge: {} hours ago", elapsed / (60 * 60))
+        }
+        None => String::from("No purge has run yet"),
+    };
+
+    bot.send_message(msg.chat.id, format!("{}\n{}", kept, last_purge))
+        .reply_to_message_id(msg.id)
+        .await?;
+    Ok(())
+}
+
 async fn blacklist(bot: Bot, msg: Message) -> HandlerResult {
     let chat_id = &msg.chat.id.to_string();
-    let config = config::get_config(&chat_id).await?;
+    let config = config::get_config(chat_id).await?;
 
     let user_level = get_user_level(
         bot.get_chat_member(
@@ -308,7 +905,7 @@ async fn blacklist(bot: Bot, msg: Message) -> HandlerResult {
 
 async fn unblacklist(bot: Bot, msg: Message) -> HandlerResult {
     let chat_id = &msg.chat.id.to_string();
-    let config = config::get_config(&chat_id).await?;
+    let config = config::get_config(chat_id).await?;
 
     let user_level = get_user_level(
         bot.get_chat_member(
@@ -379,7 +976,16 @@ fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>>
     let command_handler = teloxide::filter_command::<Command, _>().branch(
         case![State::Listen]
             .branch(case![Command::Help].endpoint(help))
-            .branch(case![Command::Markov].endpoint(generate))
+            .branch(case![Command::Markov(text)].endpoint(generate))
+            .branch(case![Command::Impersonate(text)].endpoint(impersonate))
+            .branch(case![Command::Continue(text)].endpoint(continue_sentence))
+            .branch(case![Command::Precede(text)].endpoint(precede))
+            .branch(case![Command::Score(text)].endpoint(score))
+            .branch(case![Command::OptOut].endpoint(opt_out))
+            .branch(case![Command::OptIn].endpoint(opt_in))
+            .branch(case![Command::Erase].endpoint(erase))
+            .branch(case![Command::Forget].endpoint(forget))
+            .branch(case![Command::Retention].endpoint(retention))
             .branch(case![Command::Blacklist].endpoint(blacklist))
             .branch(case![Command::Unblacklist].endpoint(unblacklist))
             .branch(case![Command::Reply(text)])
@@ -394,9 +1000,87 @@ fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>>
     dialogue::enter::<Update, dialogue::InMemStorage<State>, State, _>().branch(message_handler)
 }
 
+/// Decays and purges a model. A failed decay doesn't hold back the purge, as
+/// the purge is what keeps the retention period.
+async fn maintain(markov: &Markov, config: &MarkovConfig, name: &str) {
+    if let Err(e) = markov.maintain().await {
+        eprintln!("Couldn't decay {}: {}", name, e);
+    }
+    if config.retention_days != 0 {
+        if let Err(e) = markov.purge(config.retention_days).await {
+            eprintln!("Couldn't purge {}: {}", name, e);
+        }
+    }
+}
+
+/// Decays, prunes and purges every chat that has a model, and its users' models.
+async fn maintain_chats() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut entries = tokio::fs::read_dir("./").await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if !entry.path().join("model.db").exists() {
+            continue;
+        }
+        let chat_id = entry.file_name().to_string_lossy().to_string();
+        if let Err(e) = maintain_chat(&chat_id, &entry.path()).await {
+            eprintln!("Couldn't maintain chat {}: {}", chat_id, e);
+        }
+    }
+    Ok(())
+}
+
+/// Maintains one chat's model and its users' models. A user model that can't
+/// be opened doesn't stop the others.
+async fn maintain_chat(
+    chat_id: &str,
+    path: &std::path::Path,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let config = config::get_config(chat_id).await?;
+    maintain(
+        &create_markov(chat_id, &config).await?,
+        &config,
+        &format!("chat {}", chat_id),
+    )
+    .await;
+
+    let Ok(mut users) = tokio::fs::read_dir(path.join("users")).await else {
+        return Ok(());
+    };
+    while let Some(user) = users.next_entry().await? {
+        let path = user.path();
+        // Skips SQLite's journals and anything else that isn't a model
+        if path.extension().is_none_or(|extension| extension != "db") {
+            continue;
+        }
+
+        let markov = match SqliteDB::new(&path).await {
+            Ok(database) => {
+                configure(Markov::builder(Arc::new(database)), &config)
+                    .build()
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        match markov {
+            Ok(markov) => maintain(&markov, &config, &path.display().to_string()).await,
+            Err(e) => eprintln!("Couldn't open {}: {}", path.display(), e),
+        }
+    }
+    Ok(())
+}
+
 pub async fn start_dispatcher() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let bot = start_bot().await?;
 
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(MAINTENANCE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = maintain_chats().await {
+                eprintln!("Couldn't run maintenance: {}", e);
+            }
+        }
+    });
+
     Dispatcher::builder(bot, schema())
         .dependencies(dptree::deps![dialogue::InMemStorage::<State>::new()])
         .enable_ctrlc_handler()
@@ -1,6 +1,6 @@
-use super::super::database::{Blacklist, SqliteBlacklist};
+use super::super::database::{Blacklist, Members, OptOut, SqliteBlacklist};
 use serde::{Deserialize, Serialize};
-use teloxide::types::{ChatId, ChatMember, UserId};
+use teloxide::types::{self, ChatId, ChatMember, UserId};
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 
@@ -41,11 +41,11 @@ impl std::fmt::Display for Access {
 }
 
 async fn get_database() -> Result<SqliteBlacklist, Error> {
-    SqliteBlacklist::new(&std::path::Path::new("./chats.db")).await
+    SqliteBlacklist::new(std::path::Path::new("./chats.db")).await
 }
 
-async fn is_blacklisted(user: ChatMember, chat: ChatId) -> Result<bool, Error> {
-    let UserId(user_id) = user.user.id;
+pub async fn is_blacklisted(user: UserId, chat: ChatId) -> Result<bool, Error> {
+    let UserId(user_id) = user;
     let ChatId(chat_id) = chat;
 
     get_database().await?.is_blacklisted(chat_id, user_id).await
@@ -65,10 +65,67 @@ pub async fn unblacklist(user: ChatMember, chat: ChatId) -> Result<(), Error> {
     get_database().await?.unblacklist(chat_id, user_id).await
 }
 
+pub async fn opt_out(user: UserId, chat: ChatId) -> Result<(), Error> {
+    let UserId(user_id) = user;
+    let ChatId(chat_id) = chat;
+
+    get_database().await?.opt_out(chat_id, user_id).await
+}
+
+pub async fn opt_in(user: UserId, chat: ChatId) -> Result<(), Error> {
+    let UserId(user_id) = user;
+    let ChatId(chat_id) = chat;
+
+    get_database().await?.opt_in(chat_id, user_id).await
+}
+
+pub async fn is_opted_out(user: UserId, chat: ChatId) -> Result<bool, Error> {
+    let UserId(user_id) = user;
+    let ChatId(chat_id) = chat;
+
+    get_database().await?.is_opted_out(chat_id, user_id).await
+}
+
+pub async fn remember(user: &types::User, chat: ChatId) -> Result<(), Error> {
+    let UserId(user_id) = user.id;
+    let ChatId(chat_id) = chat;
+
+    get_database()
+        .await?
+        .remember(chat_id, user_id, user.username.as_deref())
+        .await
+}
+
+/// Looks up a user the bot has learned from by their `@username`.
+pub async fn find_member(username: &str, chat: ChatId) -> Result<Option<UserId>, Error> {
+    let ChatId(chat_id) = chat;
+    let username = username.trim_start_matches('@');
+
+    Ok(get_database()
+        .await?
+        .find_username(chat_id, username)
+        .await?
+        .map(UserId))
+}
+
+/// Users the bot has learned from in a chat, with their usernames. They may
+/// have left since. Blacklisted and opted out users are left out.
+pub async fn members(chat: ChatId) -> Result<Vec<(UserId, String)>, Error> {
+    let ChatId(chat_id) = chat;
+
+    Ok(get_database()
+        .await?
+        .get_members(chat_id)
+        .await?
+        .into_iter()
+        .map(|(user_id, username)| (UserId(user_id), username))
+        .collect())
+}
+
 pub async fn get_user_level(user: ChatMember, chat_id: ChatId) -> Result<User, Error> {
     let is_owner = user.kind.is_owner();
     let is_admin = user.kind.is_administrator();
-    let is_blacklisted = is_blacklisted(user, chat_id).await?;
+    let is_blacklisted = is_blacklisted(user.user.id, chat_id).await?;
 
     match (is_owner, is_admin, is_blacklisted) {
         (true, _, _) => Ok(User::Owner),
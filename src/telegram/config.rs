@@ -1,5 +1,13 @@
+use super::super::markov::classes::{ClassPolicies, ClassPolicy};
+use super::super::markov::constraints::{Constraints, Ending};
+use super::super::markov::limits::Limits;
+use super::super::markov::postprocess::PostProcessing;
+use super::super::markov::rank::RankerType;
+use super::super::markov::sampling::Sampling;
+use super::super::markov::tokenizer::TokenizerType;
 use super::super::markov::{MarkovType, ReplyMode};
 use super::chat;
+use super::mentions::MentionPolicy;
 use serde::{Deserialize, Serialize};
 use tokio::fs::{create_dir_all, read_to_string, File};
 use tokio::io::AsyncWriteExt;
@@ -24,6 +32,7 @@ pub struct MarkovAccess {
 pub struct AdminCmdAccess {
     config: Option<chat::Access>,
     blacklist: Option<chat::Access>,
+    forget: Option<chat::Access>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -38,6 +47,35 @@ pub struct MarkovConfigToml {
     chance: Option<u64>,
     reply_mode: Option<ReplyMode>,
     separate_newline: Option<bool>,
+    half_life_days: Option<u64>,
+    attribution: Option<bool>,
+    min_user_lines: Option<u64>,
+    retention_days: Option<u64>,
+    min_tokens: Option<u64>,
+    max_tokens: Option<u64>,
+    ending: Option<Ending>,
+    retries: Option<u64>,
+    max_shared_ngram: Option<u64>,
+    token_limit: Option<u64>,
+    cycle_window: Option<u64>,
+    timeout_ms: Option<u64>,
+    temperature: Option<f64>,
+    top_k: Option<u64>,
+    top_p: Option<f64>,
+    candidates: Option<u64>,
+    ranker: Option<RankerType>,
+    tokenizer: Option<TokenizerType>,
+    url_policy: Option<ClassPolicy>,
+    mention_policy: Option<ClassPolicy>,
+    hashtag_policy: Option<ClassPolicy>,
+    command_policy: Option<ClassPolicy>,
+    number_policy: Option<ClassPolicy>,
+    mentions: Option<MentionPolicy>,
+    trim_leading_punctuation: Option<bool>,
+    collapse_punctuation: Option<bool>,
+    balance_brackets: Option<bool>,
+    terminal_punctuation: Option<bool>,
+    capitalize: Option<bool>,
     access: Option<Access>,
 }
 
@@ -52,6 +90,7 @@ pub struct MarkovAccessConfig {
 pub struct AdminCmdAccessConfig {
     pub config: chat::Access,
     pub blacklist: chat::Access,
+    pub forget: chat::Access,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -65,9 +104,86 @@ pub struct MarkovConfig {
     pub chance: u64,
     pub reply_mode: ReplyMode,
     pub separate_newline: bool,
+    pub half_life_days: u64,
+    pub attribution: bool,
+    pub min_user_lines: u64,
+    pub retention_days: u64,
+    pub min_tokens: u64,
+    pub max_tokens: u64,
+    pub ending: Ending,
+    pub retries: u64,
+    pub max_shared_ngram: u64,
+    pub token_limit: u64,
+    pub cycle_window: u64,
+    pub timeout_ms: u64,
+    pub temperature: f64,
+    pub top_k: u64,
+    pub top_p: f64,
+    pub candidates: u64,
+    pub ranker: RankerType,
+    pub tokenizer: TokenizerType,
+    pub url_policy: ClassPolicy,
+    pub mention_policy: ClassPolicy,
+    pub hashtag_policy: ClassPolicy,
+    pub command_policy: ClassPolicy,
+    pub number_policy: ClassPolicy,
+    pub mentions: MentionPolicy,
+    pub trim_leading_punctuation: bool,
+    pub collapse_punctuation: bool,
+    pub balance_brackets: bool,
+    pub terminal_punctuation: bool,
+    pub capitalize: bool,
     pub access: AccessConfig,
 }
 
+impl MarkovConfig {
+    pub fn constraints(&self) -> Constraints {
+        Constraints {
+            min_tokens: self.min_tokens,
+            max_tokens: self.max_tokens,
+            ending: self.ending,
+            retries: self.retries,
+            max_shared_ngram: self.max_shared_ngram,
+        }
+    }
+
+    pub fn limits(&self) -> Limits {
+        Limits {
+            token_limit: self.token_limit,
+            cycle_window: self.cycle_window,
+            timeout_ms: self.timeout_ms,
+        }
+    }
+
+    pub fn sampling(&self) -> Sampling {
+        Sampling {
+            temperature: self.temperature,
+            top_k: self.top_k,
+            top_p: self.top_p,
+        }
+    }
+
+    pub fn classes(&self) -> ClassPolicies {
+        ClassPolicies {
+            urls: self.url_policy,
+            mentions: self.mention_policy,
+            hashtags: self.hashtag_policy,
+            commands: self.command_policy,
+            numbers: self.number_policy,
+        }
+    }
+
+    pub fn post_processing(&self) -> PostProcessing {
+        PostProcessing {
+            trim_leading_punctuation: self.trim_leading_punctuation,
+            collapse_punctuation: self.collapse_punctuation,
+            balance_brackets: self.balance_brackets,
+            terminal_punctuation: self.terminal_punctuation,
+            capitalize: self.capitalize,
+        }
+    }
+}
+
 type Error = Box<dyn std::error::Error + Send + Sync>;
 
 macro_rules! get_or_default {
@@ -93,6 +209,73 @@ async fn set_missing_config(
     let markov_type = get_or_default!(has_missing, configtoml.markov_type, MarkovType::default());
     let reply_mode = get_or_default!(has_missing, configtoml.reply_mode, ReplyMode::default());
     let separate_newline = get_or_default!(has_missing, configtoml.separate_newline, true);
+    let half_life_days = get_or_default!(
+        has_missing,
+        configtoml.half_life_days,
+        DEFAULT_HALF_LIFE_DAYS
+    );
+    let attribution = get_or_default!(has_missing, configtoml.attribution, DEFAULT_ATTRIBUTION);
+    let min_user_lines = get_or_default!(
+        has_missing,
+        configtoml.min_user_lines,
+        DEFAULT_MIN_USER_LINES
+    );
+    let retention_days = get_or_default!(
+        has_missing,
+        configtoml.retention_days,
+        DEFAULT_RETENTION_DAYS
+    );
+    let min_tokens = get_or_default!(has_missing, configtoml.min_tokens, DEFAULT_MIN_TOKENS);
+    let max_tokens = get_or_default!(has_missing, configtoml.max_tokens, DEFAULT_MAX_TOKENS);
+    let ending = get_or_default!(has_missing, configtoml.ending, DEFAULT_ENDING);
+    let retries = get_or_default!(has_missing, configtoml.retries, DEFAULT_RETRIES);
+    let max_shared_ngram = get_or_default!(
+        has_missing,
+        configtoml.max_shared_ngram,
+        DEFAULT_MAX_SHARED_NGRAM
+    );
+    let token_limit = get_or_default!(has_missing, configtoml.token_limit, DEFAULT_TOKEN_LIMIT);
+    let cycle_window = get_or_default!(has_missing, configtoml.cycle_window, DEFAULT_CYCLE_WINDOW);
+    let timeout_ms = get_or_default!(has_missing, configtoml.timeout_ms, DEFAULT_TIMEOUT_MS);
+    let temperature = get_or_default!(has_missing, configtoml.temperature, DEFAULT_TEMPERATURE);
+    let top_k = get_or_default!(has_missing, configtoml.top_k, DEFAULT_TOP_K);
+    let top_p = get_or_default!(has_missing, configtoml.top_p, DEFAULT_TOP_P);
+    Sampling::check_temperature(temperature)?;
+    Sampling::check_top_p(top_p)?;
+    let candidates = get_or_default!(has_missing, configtoml.candidates, DEFAULT_CANDIDATES);
+    let ranker = get_or_default!(has_missing, configtoml.ranker, DEFAULT_RANKER);
+    let tokenizer = get_or_default!(has_missing, configtoml.tokenizer, DEFAULT_TOKENIZER);
+    let url_policy = get_or_default!(has_missing, configtoml.url_policy, DEFAULT_CLASS_POLICY);
+    let mention_policy =
+        get_or_default!(has_missing, configtoml.mention_policy, DEFAULT_CLASS_POLICY);
+    let hashtag_policy =
+        get_or_default!(has_missing, configtoml.hashtag_policy, DEFAULT_CLASS_POLICY);
+    let command_policy =
+        get_or_default!(has_missing, configtoml.command_policy, DEFAULT_CLASS_POLICY);
+    let number_policy =
+        get_or_default!(has_missing, configtoml.number_policy, DEFAULT_CLASS_POLICY);
+    let mentions = get_or_default!(has_missing, configtoml.mentions, DEFAULT_MENTIONS);
+    let trim_leading_punctuation = get_or_default!(
+        has_missing,
+        configtoml.trim_leading_punctuation,
+        DEFAULT_TRIM_LEADING_PUNCTUATION
+    );
+    let collapse_punctuation = get_or_default!(
+        has_missing,
+        configtoml.collapse_punctuation,
+        DEFAULT_COLLAPSE_PUNCTUATION
+    );
+    let balance_brackets = get_or_default!(
+        has_missing,
+        configtoml.balance_brackets,
+        DEFAULT_BALANCE_BRACKETS
+    );
+    let terminal_punctuation = get_or_default!(
+        has_missing,
+        configtoml.terminal_punctuation,
+        DEFAULT_TERMINAL_PUNCTUATION
+    );
+    let capitalize = get_or_default!(has_missing, configtoml.capitalize, DEFAULT_CAPITALIZE);
     //SCHIZOPHRENIC CODE!!!
     let access = match &mut configtoml.access {
         Some(v) => {
@@ -121,6 +304,7 @@ async fn set_missing_config(
                         v.blacklist,
                         DEFAULT_ADMIN_CMD_ACCESS_BLACKLIST
                     ),
+                    forget: get_or_default!(has_missing, v.forget, DEFAULT_ADMIN_CMD_ACCESS_FORGET),
                 },
                 None => {
                     has_missing = true;
@@ -151,6 +335,35 @@ async fn set_missing_config(
         markov_type,
         reply_mode,
         separate_newline,
+        half_life_days,
+        attribution,
+        min_user_lines,
+        retention_days,
+        min_tokens,
+        max_tokens,
+        ending,
+        retries,
+        max_shared_ngram,
+        token_limit,
+        cycle_window,
+        timeout_ms,
+        temperature,
+        top_k,
+        top_p,
+        candidates,
+        ranker,
+        tokenizer,
+        url_policy,
+        mention_policy,
+        hashtag_policy,
+        command_policy,
+        number_policy,
+        mentions,
+        trim_leading_punctuation,
+        collapse_punctuation,
+        balance_brackets,
+        terminal_punctuation,
+        capitalize,
         access,
     })
 }
@@ -188,11 +401,8 @@ pub async fn get_config(filename: &str) -> Result<MarkovConfig, Error> {
     let dir = std::path::Path::new(&dir_name);
 
     let result = read_to_string(path).await;
-    let string;
-    match result {
-        Ok(s) => {
-            string = s;
-        }
+    let string = match result {
+        Ok(s) => s,
         Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {
             let config = DEFAULT_CONFIG_TOML;
 
@@ -208,7 +418,7 @@ pub async fn get_config(filename: &str) -> Result<MarkovConfig, Error> {
 
     let mut config: MarkovConfigToml = toml::from_str(&string)?;
 
-    Ok(set_missing_config(&mut config, path).await?)
+    set_missing_config(&mut config, path).await
 }
 
 async fn write_missing(string: &str, path: &std::path::Path) -> Result<(), Error> {
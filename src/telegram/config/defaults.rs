@@ -1,4 +1,23 @@
+pub use super::super::super::markov::classes::DEFAULT_CLASS_POLICY;
+pub use super::super::super::markov::constraints::{
+    DEFAULT_ENDING, DEFAULT_MAX_SHARED_NGRAM, DEFAULT_MAX_TOKENS, DEFAULT_MIN_TOKENS,
+    DEFAULT_RETRIES,
+};
+pub use super::super::super::markov::limits::{
+    DEFAULT_CYCLE_WINDOW, DEFAULT_TIMEOUT_MS, DEFAULT_TOKEN_LIMIT,
+};
+pub use super::super::super::markov::postprocess::{
+    DEFAULT_BALANCE_BRACKETS, DEFAULT_CAPITALIZE, DEFAULT_COLLAPSE_PUNCTUATION,
+    DEFAULT_TERMINAL_PUNCTUATION, DEFAULT_TRIM_LEADING_PUNCTUATION,
+};
+pub use super::super::super::markov::rank::{DEFAULT_CANDIDATES, DEFAULT_RANKER};
+pub use super::super::super::markov::sampling::{
+    DEFAULT_TEMPERATURE, DEFAULT_TOP_K, DEFAULT_TOP_P,
+};
+pub use super::super::super::markov::tokenizer::DEFAULT_TOKENIZER;
+pub use super::super::super::markov::DEFAULT_HALF_LIFE_DAYS;
 use super::super::super::markov::{DEFAULT_MARKOV_TYPE, DEFAULT_REPLY_MODE};
+use super::super::mentions::MentionPolicy;
 use super::{
     chat, Access, AccessConfig, AdminCmdAccess, AdminCmdAccessConfig, MarkovAccess,
     MarkovAccessConfig, MarkovConfig, MarkovConfigToml,
@@ -10,6 +29,10 @@ pub const DEFAULT_MARKOV_ACCESS_APPEND: chat::Access = chat::Access::All;
 pub const DEFAULT_MARKOV_ACCESS_GENERATE: chat::Access = chat::Access::All;
 pub const DEFAULT_MARKOV_ACCESS_REPLY: chat::Access = chat::Access::All;
 pub const DEFAULT_SEPARATE_NEWLINE: bool = true;
+pub const DEFAULT_ATTRIBUTION: bool = false;
+pub const DEFAULT_MIN_USER_LINES: u64 = 20;
+pub const DEFAULT_RETENTION_DAYS: u64 = 0;
+pub const DEFAULT_MENTIONS: MentionPolicy = MentionPolicy::Keep;
 
 pub const DEFAULT_MARKOV_ACCESS: MarkovAccessConfig = MarkovAccessConfig {
     append: DEFAULT_MARKOV_ACCESS_APPEND,
@@ -19,10 +42,12 @@ pub const DEFAULT_MARKOV_ACCESS: MarkovAccessConfig = MarkovAccessConfig {
 
 pub const DEFAULT_ADMIN_CMD_ACCESS_CONFIG: chat::Access = chat::Access::Admins;
 pub const DEFAULT_ADMIN_CMD_ACCESS_BLACKLIST: chat::Access = chat::Access::Admins;
+pub const DEFAULT_ADMIN_CMD_ACCESS_FORGET: chat::Access = chat::Access::Admins;
 
 pub const DEFAULT_ADMIN_CMD_ACCESS: AdminCmdAccessConfig = AdminCmdAccessConfig {
     config: DEFAULT_ADMIN_CMD_ACCESS_CONFIG,
     blacklist: DEFAULT_ADMIN_CMD_ACCESS_BLACKLIST,
+    forget: DEFAULT_ADMIN_CMD_ACCESS_FORGET,
 };
 
 pub const DEFAULT_ACCESS: AccessConfig = AccessConfig {
@@ -35,6 +60,35 @@ pub const DEFAULT_CONFIG: MarkovConfig = MarkovConfig {
     chance: DEFAULT_CHANCE,
     reply_mode: DEFAULT_REPLY_MODE,
     separate_newline: DEFAULT_SEPARATE_NEWLINE,
+    half_life_days: DEFAULT_HALF_LIFE_DAYS,
+    attribution: DEFAULT_ATTRIBUTION,
+    min_user_lines: DEFAULT_MIN_USER_LINES,
+    retention_days: DEFAULT_RETENTION_DAYS,
+    min_tokens: DEFAULT_MIN_TOKENS,
+    max_tokens: DEFAULT_MAX_TOKENS,
+    ending: DEFAULT_ENDING,
+    retries: DEFAULT_RETRIES,
+    max_shared_ngram: DEFAULT_MAX_SHARED_NGRAM,
+    token_limit: DEFAULT_TOKEN_LIMIT,
+    cycle_window: DEFAULT_CYCLE_WINDOW,
+    timeout_ms: DEFAULT_TIMEOUT_MS,
+    temperature: DEFAULT_TEMPERATURE,
+    top_k: DEFAULT_TOP_K,
+    top_p: DEFAULT_TOP_P,
+    candidates: DEFAULT_CANDIDATES,
+    ranker: DEFAULT_RANKER,
+    tokenizer: DEFAULT_TOKENIZER,
+    url_policy: DEFAULT_CLASS_POLICY,
+    mention_policy: DEFAULT_CLASS_POLICY,
+    hashtag_policy: DEFAULT_CLASS_POLICY,
+    command_policy: DEFAULT_CLASS_POLICY,
+    number_policy: DEFAULT_CLASS_POLICY,
+    mentions: DEFAULT_MENTIONS,
+    trim_leading_punctuation: DEFAULT_TRIM_LEADING_PUNCTUATION,
+    collapse_punctuation: DEFAULT_COLLAPSE_PUNCTUATION,
+    balance_brackets: DEFAULT_BALANCE_BRACKETS,
+    terminal_punctuation: DEFAULT_TERMINAL_PUNCTUATION,
+    capitalize: DEFAULT_CAPITALIZE,
     access: DEFAULT_ACCESS,
 };
 
@@ -47,6 +101,7 @@ pub const DEFAULT_MARKOV_ACCESS_TOML: MarkovAccess = MarkovAccess {
 pub const DEFAULT_ADMIN_CMD_ACCESS_TOML: AdminCmdAccess = AdminCmdAccess {
     config: Some(DEFAULT_ADMIN_CMD_ACCESS_CONFIG),
     blacklist: Some(DEFAULT_ADMIN_CMD_ACCESS_BLACKLIST),
+    forget: Some(DEFAULT_ADMIN_CMD_ACCESS_FORGET),
 };
 
 pub const DEFAULT_ACCESS_TOML: Access = Access {
@@ -59,5 +114,34 @@ pub const DEFAULT_CONFIG_TOML: MarkovConfigToml = MarkovConfigToml {
     chance: Some(DEFAULT_CHANCE),
     reply_mode: Some(DEFAULT_REPLY_MODE),
     separate_newline: Some(DEFAULT_SEPARATE_NEWLINE),
+    half_life_days: Some(DEFAULT_HALF_LIFE_DAYS),
+    attribution: Some(DEFAULT_ATTRIBUTION),
+    min_user_lines: Some(DEFAULT_MIN_USER_LINES),
+    retention_days: Some(DEFAULT_RETENTION_DAYS),
+    min_tokens: Some(DEFAULT_MIN_TOKENS),
+    max_tokens: Some(DEFAULT_MAX_TOKENS),
+    ending: Some(DEFAULT_ENDING),
+    retries: Some(DEFAULT_RETRIES),
+    max_shared_ngram: Some(DEFAULT_MAX_SHARED_NGRAM),
+    token_limit: Some(DEFAULT_TOKEN_LIMIT),
+    cycle_window: Some(DEFAULT_CYCLE_WINDOW),
+    timeout_ms: Some(DEFAULT_TIMEOUT_MS),
+    temperature: Some(DEFAULT_TEMPERATURE),
+    top_k: Some(DEFAULT_TOP_K),
+    top_p: Some(DEFAULT_TOP_P),
+    candidates: Some(DEFAULT_CANDIDATES),
+    ranker: Some(DEFAULT_RANKER),
+    tokenizer: Some(DEFAULT_TOKENIZER),
+    url_policy: Some(DEFAULT_CLASS_POLICY),
+    mention_policy: Some(DEFAULT_CLASS_POLICY),
+    hashtag_policy: Some(DEFAULT_CLASS_POLICY),
+    command_policy: Some(DEFAULT_CLASS_POLICY),
+    number_policy: Some(DEFAULT_CLASS_POLICY),
+    mentions: Some(DEFAULT_MENTIONS),
+    trim_leading_punctuation: Some(DEFAULT_TRIM_LEADING_PUNCTUATION),
+    collapse_punctuation: Some(DEFAULT_COLLAPSE_PUNCTUATION),
+    balance_brackets: Some(DEFAULT_BALANCE_BRACKETS),
+    terminal_punctuation: Some(DEFAULT_TERMINAL_PUNCTUATION),
+    capitalize: Some(DEFAULT_CAPITALIZE),
     access: Some(DEFAULT_ACCESS_TOML),
 };
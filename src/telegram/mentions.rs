@@ -0,0 +1,163 @@
+use super::chat;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use teloxide::prelude::*;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Put after the `@` of a de-linked mention so Telegram doesn't ping anyone.
+const ZERO_WIDTH_SPACE: char = '\u{200B}';
+
+/// Most members looked up per message, as every lookup is a request to
+/// Telegram.
+const MAX_MEMBER_LOOKUPS: usize = 10;
+
+/// What happens to `@username` mentions in generated text before it is sent.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum MentionPolicy {
+    Keep,
+    Strip,
+    /// Breaks the mention up with a zero width space, so it reads the same but
+    /// doesn't notify anyone.
+    Delink,
+    /// Mentions a random user the bot has learned from who is still in the
+    /// chat. Users are only remembered while `attribution` is on.
+    Random,
+}
+
+/// Byte ranges of the `@username` mentions in `text`.
+fn find_mentions(text: &str) -> Vec<(usize, usize)> {
+    let mut mentions = vec![];
+    let mut previous = None;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let at_boundary = previous.is_none_or(|p: char| !p.is_alphanumeric() && p != '_');
+        previous = Some(c);
+        if c != '@' || !at_boundary {
+            continue;
+        }
+
+        let mut end = start + 1;
+        while let Some(&(index, c)) = chars.peek() {
+            if !c.is_ascii_alphanumeric() && c != '_' {
+                break;
+            }
+            end = index + c.len_utf8();
+            previous = Some(c);
+            chars.next();
+        }
+        if end > start + 1 {
+            mentions.push((start, end));
+        }
+    }
+    mentions
+}
+
+/// Up to `count` usernames of remembered users who are still in the chat,
+/// picked from a random few of them.
+async fn present_members(bot: &Bot, chat: ChatId, count: usize) -> Result<Vec<String>, Error> {
+    let mut members = chat::members(chat).await?;
+    members.shuffle(&mut rand::thread_rng());
+
+    let mut present = vec![];
+    for (user_id, username) in members.into_iter().take(MAX_MEMBER_LOOKUPS) {
+        if present.len() >= count {
+            break;
+        }
+        if let Ok(member) = bot.get_chat_member(chat, user_id).await {
+            if member.kind.is_present() {
+                present.push(username);
+            }
+        }
+    }
+    Ok(present)
+}
+
+/// Applies a chat's mention policy to a generated message.
+pub async fn apply(
+    bot: &Bot,
+    chat: ChatId,
+    text: String,
+    policy: MentionPolicy,
+) -> Result<String, Error> {
+    let mentions = find_mentions(&text);
+    if mentions.is_empty() {
+        return Ok(text);
+    }
+
+    let members = match policy {
+        MentionPolicy::Keep => return Ok(text),
+        MentionPolicy::Random => present_members(bot, chat, mentions.len()).await?,
+        _ => vec![],
+    };
+
+    let mut result = String::with_capacity(text.len());
+    let mut last = 0;
+    for (index, &(start, end)) in mentions.iter().enumerate() {
+        result.push_str(&text[last..start]);
+        match policy {
+            MentionPolicy::Strip => {
+                // Takes a space along so no gap is left behind
+                let next = text[end..].chars().next();
+                if next.is_none_or(|c| !c.is_alphanumeric()) && result.ends_with(' ') {
+                    result.pop();
+                } else if result.is_empty() && next == Some(' ') {
+                    last = end + 1;
+                    continue;
+                }
+            }
+            // Falls back to de-linking when nobody can be found
+            MentionPolicy::Random if !members.is_empty() => {
+                result.push('@');
+                result.push_str(&members[index % members.len()]);
+            }
+            _ => {
+                result.push('@');
+                result.push(ZERO_WIDTH_SPACE);
+                result.push_str(&text[start + 1..end]);
+            }
+        }
+        last = end;
+    }
+    result.push_str(&text[last..]);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mentions(text: &str) -> Vec<&str> {
+        find_mentions(text)
+            .into_iter()
+            .map(|(start, end)| &text[start..end])
+            .collect()
+    }
+
+    #[test]
+    fn finds_mentions() {
+        assert_eq!(mentions("@alice and @bob_2"), ["@alice", "@bob_2"]);
+    }
+
+    #[test]
+    fn ends_mentions_at_punctuation() {
+        assert_eq!(mentions("hi @alice, (@bob)!"), ["@alice", "@bob"]);
+    }
+
+    #[test]
+    fn ignores_email_addresses() {
+        assert!(mentions("mail me at me@example.com").is_empty());
+    }
+
+    #[test]
+    fn ignores_a_lone_at_sign() {
+        assert!(mentions("meet @ noon, @").is_empty());
+    }
+
+    #[test]
+    fn handles_non_ascii_text_around_mentions() {
+        assert_eq!(mentions("привет @alice ёж"), ["@alice"]);
+        assert!(mentions("ёж@alice").is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+use super::super::markov::constraints::Ending;
+use super::super::markov::sampling::Sampling;
+use super::super::markov::MarkovBuilder;
+use super::config::MarkovConfig;
+
+#[derive(Default)]
+pub struct GenerateOptions {
+    pub seed: Option<u64>,
+    pub min_tokens: Option<u64>,
+    pub max_tokens: Option<u64>,
+    pub ending: Option<Ending>,
+    pub temperature: Option<f64>,
+    pub top_k: Option<u64>,
+    pub top_p: Option<f64>,
+    pub phrase: String,
+}
+
+macro_rules! parse_value {
+    ($name:expr, $value:expr) => {
+        Some(
+            $value
+                .parse()
+                .map_err(|_| format!("Invalid {}: {}", $name, $value))?,
+        )
+    };
+}
+
+impl GenerateOptions {
+    /// Parses arguments such as `/markov seed=42 min=5 max=30 temp=1.5 end=ask`. Words
+    /// that are not options make up the phrase to start from.
+    pub fn parse(text: &str) -> Result<GenerateOptions, String> {
+        let mut options = GenerateOptions::default();
+
+        for arg in text.split_whitespace() {
+            match arg.split_once('=') {
+                Some(("seed", value)) => options.seed = parse_value!("seed", value),
+                Some(("min", value)) => options.min_tokens = parse_value!("minimum", value),
+                Some(("max", value)) => options.max_tokens = parse_value!("maximum", value),
+                Some(("temp", value)) => options.temperature = parse_value!("temperature", value),
+                Some(("topk", value)) => options.top_k = parse_value!("top-k", value),
+                Some(("topp", value)) => options.top_p = parse_value!("top-p", value),
+                Some(("end", value)) => {
+                    options.ending = Some(match value {
+                        "any" => Ending::Any,
+                        "ask" => Ending::Question,
+                        "exclaim" => Ending::Exclamation,
+                        "state" => Ending::Statement,
+                        _ => return Err(format!("Invalid ending: {}", value)),
+                    })
+                }
+                Some(_) => {
+                    return Err(format!("Unknown option: {}", arg));
+                }
+                None => {
+                    if !options.phrase.is_empty() {
+                        options.phrase.push(' ');
+                    }
+                    options.phrase.push_str(arg);
+                }
+            }
+        }
+
+        if let Some(temperature) = options.temperature {
+            Sampling::check_temperature(temperature)?;
+        }
+        if let Some(top_p) = options.top_p {
+            Sampling::check_top_p(top_p)?;
+        }
+
+        Ok(options)
+    }
+
+    pub fn apply(&self, mut builder: MarkovBuilder, config: &MarkovConfig) -> MarkovBuilder {
+        if let Some(seed) = self.seed {
+            builder = builder.seed(seed);
+        }
+
+        let mut constraints = config.constraints();
+        if let Some(min_tokens) = self.min_tokens {
+            constraints.min_tokens = min_tokens;
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            constraints.max_tokens = max_tokens;
+        }
+        if let Some(ending) = self.ending {
+            constraints.ending = ending;
+        }
+
+        let mut sampling = config.sampling();
+        if let Some(temperature) = self.temperature {
+            sampling.temperature = temperature;
+        }
+        if let Some(top_k) = self.top_k {
+            sampling.top_k = top_k;
+        }
+        if let Some(top_p) = self.top_p {
+            sampling.top_p = top_p;
+        }
+
+        builder.constraints(constraints).sampling(sampling)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ending_words_are_part_of_the_phrase() {
+        let options = GenerateOptions::parse("state of the art").unwrap();
+        assert!(options.ending.is_none());
+        assert_eq!(options.phrase, "state of the art");
+    }
+
+    #[test]
+    fn ending_is_set_with_end() {
+        let options = GenerateOptions::parse("end=ask what is").unwrap();
+        assert!(options.ending == Some(Ending::Question));
+        assert_eq!(options.phrase, "what is");
+        assert!(GenerateOptions::parse("end=maybe").is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use sneedov::database::{Database, SqliteDB};
+use sneedov::markov::constraints::Constraints;
+use sneedov::markov::{Markov, ANONYMOUS_USER};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const LINES: [&str; 6] = ["a x b", "c x b", "c x d", "a x b y x d", "c y x b", "a y d"];
+
+async fn train() -> Result<Arc<SqliteDB>, Error> {
+    let database = Arc::new(SqliteDB::new(std::path::Path::new(":memory:")).await?);
+    let markov = Markov::new(database.clone()).await?;
+    for line in LINES {
+        markov.append_line(line).await?;
+    }
+    Ok(database)
+}
+
+async fn id(database: &SqliteDB, keyword: &str, string: &str) -> Result<u64, Error> {
+    Ok(database
+        .get_index((keyword, string))
+        .await?
+        .expect("the word should have been learned"))
+}
+
+fn sorted(vec: Vec<(u64, f64)>) -> Vec<(u64, f64)> {
+    let mut vec = vec;
+    vec.sort_by_key(|item| item.0);
+    vec
+}
+
+#[tokio::test]
+async fn backward_single_counts_every_predecessor() -> Result<(), Error> {
+    let database = train().await?;
+    let x = id(&database, "middle", "x").await?;
+    let a = id(&database, "first", "a").await?;
+    let c = id(&database, "first", "c").await?;
+    let y = id(&database, "middle", "y").await?;
+
+    let prev = database.get_prev_single_occurrences(x).await?;
+    assert_eq!(sorted(prev), sorted(vec![(a, 2.0), (c, 2.0), (y, 2.0)]));
+    Ok(())
+}
+
+#[tokio::test]
+async fn backward_double_counts_every_predecessor() -> Result<(), Error> {
+    let database = train().await?;
+    let x = id(&database, "middle", "x").await?;
+    let b = id(&database, "last", "b").await?;
+    let a = id(&database, "first", "a").await?;
+    let c = id(&database, "first", "c").await?;
+    let y = id(&database, "middle", "y").await?;
+
+    let prev = database.get_prev_double_occurrences(x, b).await?;
+    assert_eq!(sorted(prev), sorted(vec![(a, 1.0), (c, 1.0), (y, 1.0)]));
+    Ok(())
+}
+
+#[tokio::test]
+async fn forward_single_counts_every_successor() -> Result<(), Error> {
+    let database = train().await?;
+    let x = id(&database, "middle", "x").await?;
+    let b = id(&database, "last", "b").await?;
+    let d = id(&database, "last", "d").await?;
+    let middle_b = id(&database, "middle", "b").await?;
+
+    let next = database.get_single_occurrences(x).await?;
+    assert_eq!(
+        sorted(next),
+        sorted(vec![(b, 3.0), (d, 2.0), (middle_b, 1.0)])
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn forward_and_backward_bigrams_agree() -> Result<(), Error> {
+    let database = train().await?;
+
+    let mut words = vec![];
+    for (keyword, string) in [
+        ("first", "a"),
+        ("first", "c"),
+        ("middle", "x"),
+        ("middle", "y"),
+        ("middle", "b"),
+        ("last", "b"),
+        ("last", "d"),
+    ] {
+        words.push(id(&database, keyword, string).await?);
+    }
+
+    for &word in &words {
+        let forward: BTreeMap<u64, f64> = database
+            .get_single_occurrences(word)
+            .await?
+            .into_iter()
+            .collect();
+
+        for &other in &words {
+            let backward: BTreeMap<u64, f64> = database
+                .get_prev_single_occurrences(other)
+                .await?
+                .into_iter()
+                .collect();
+            assert_eq!(forward.get(&other), backward.get(&word));
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn purging_a_migrated_model_keeps_its_old_lines() -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("sneedov-migrate-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    // The schema from before weights, timestamps and n-grams were stored
+    sqlite::open(&path)?.execute(
+        "
+        CREATE TABLE Words(
+            id INTEGER PRIMARY KEY, keyword VARCHAR(20), string VARCHAR(255),
+            UNIQUE(keyword,string));
+        CREATE TABLE Occurrence(
+            prev INT NOT NULL, curr INT NOT NULL, next INT NOT NULL, occurrences INT,
+            UNIQUE(prev, curr, next));
+        INSERT INTO Words VALUES(1, 'end', ''), (2, 'start', ''), (3, 'first', 'a'), (4, 'last', 'b');
+        INSERT INTO Occurrence VALUES(2, 2, 3, 1), (2, 3, 4, 1), (3, 4, 1, 1);
+        ",
+    )?;
+
+    let database = Arc::new(SqliteDB::new(&path).await?);
+    let markov = Markov::new(database.clone()).await?;
+    markov.purge(1).await?;
+
+    let next = database.get_single_occurrences(3).await?;
+    std::fs::remove_file(&path)?;
+    assert_eq!(next, vec![(4, 1.0)]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn forgetting_a_line_never_said_leaves_the_model_alone() -> Result<(), Error> {
+    let database = Arc::new(SqliteDB::new(std::path::Path::new(":memory:")).await?);
+    let markov = Markov::new(database.clone()).await?;
+    markov.append_line("p q r s t u v w y").await?;
+    markov.append_line("z q r s t u v w x").await?;
+
+    // Every n-gram of this line was learned, just from the two lines above
+    assert!(!markov.remove_line("p q r s t u v w x").await?);
+    assert!(markov.remove_line("p q r s t u v w y").await?);
+    assert!(markov.remove_line("z q r s t u v w x").await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn maintenance_on_a_locked_model_fails_without_changing_it() -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("sneedov-locked-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let database = Arc::new(SqliteDB::new(&path).await?);
+    let markov = Markov::new(database.clone()).await?;
+    for line in LINES {
+        markov.append_line(line).await?;
+    }
+    database.set_half_life(86400).await?;
+    let x = id(&database, "middle", "x").await?;
+    let before = sorted(database.get_single_occurrences(x).await?);
+
+    // Another connection to the same model, in the middle of writing
+    let other = sqlite::open(&path)?;
+    other.execute("BEGIN IMMEDIATE;")?;
+    let locked = database.maintain(0.5).await;
+    other.execute("ROLLBACK;")?;
+
+    let after = sorted(database.get_single_occurrences(x).await?);
+    std::fs::remove_file(&path)?;
+    assert!(locked.is_err());
+    assert_eq!(before.len(), after.len());
+    for ((index, weight), (other_index, other_weight)) in before.iter().zip(&after) {
+        assert_eq!(index, other_index);
+        assert!((weight - other_weight).abs() < 1e-6);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn a_line_outlives_its_faded_fingerprints() -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("sneedov-faded-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let database = Arc::new(SqliteDB::new(&path).await?);
+    let markov = Markov::new(database.clone()).await?;
+    markov.append_line("p q r s").await?;
+    database.set_half_life(86400).await?;
+
+    // Learned long enough ago for its fingerprints to have faded, while other
+    // lines kept its n-grams well above the threshold
+    sqlite::open(&path)?.execute(
+        "UPDATE Fingerprint SET last_seen = 0;
+        UPDATE Line SET last_seen = 0;
+        UPDATE Occurrence SET weight = 100;",
+    )?;
+    database.maintain(0.05).await?;
+
+    let removed = markov.remove_line("p q r s").await?;
+    std::fs::remove_file(&path)?;
+    assert!(removed);
+    Ok(())
+}
+
+#[tokio::test]
+async fn purging_takes_back_old_anonymous_lines_exactly() -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("sneedov-anonymous-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let database = Arc::new(SqliteDB::new(&path).await?);
+    let markov = Markov::new(database.clone()).await?;
+    markov.append_attributed("p q r", ANONYMOUS_USER).await?;
+    sqlite::open(&path)?.execute("UPDATE Contribution SET timestamp = 0;")?;
+    // Seen again just now, so only the record says "p q r" itself is old
+    markov.append_attributed("p q r", ANONYMOUS_USER).await?;
+    markov.purge(1).await?;
+
+    let kept = markov.remove_line("p q r").await?;
+    let twice = markov.remove_line("p q r").await?;
+    std::fs::remove_file(&path)?;
+    assert!(kept);
+    assert!(!twice);
+    Ok(())
+}
+
+#[tokio::test]
+async fn runs_are_only_fingerprinted_for_the_originality_check() -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("sneedov-runs-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let count = || -> Result<i64, Error> {
+        let connection = sqlite::open(&path)?;
+        let mut statement = connection.prepare("SELECT COUNT(*) AS runs FROM Fingerprint;")?;
+        statement.next()?;
+        Ok(statement.read::<i64, _>("runs")?)
+    };
+
+    let database = Arc::new(SqliteDB::new(&path).await?);
+    Markov::new(database.clone())
+        .await?
+        .append_line("p q r s t")
+        .await?;
+    let unchecked = count()?;
+
+    let checked = Markov::builder(database.clone())
+        .constraints(Constraints {
+            max_shared_ngram: 2,
+            ..Constraints::default()
+        })
+        .build()
+        .await?;
+    checked.append_line("p q r s t").await?;
+    let three_word_runs = count()?;
+    checked.remove_line("p q r s t").await?;
+    let removed = count()?;
+
+    std::fs::remove_file(&path)?;
+    assert_eq!(unchecked, 0);
+    assert_eq!(three_word_runs, 3);
+    assert_eq!(removed, 0);
+    Ok(())
+}
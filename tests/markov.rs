@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use sneedov::database::SqliteDB;
+use sneedov::markov::error::GenerationError;
+use sneedov::markov::tokenizer::TokenizerType;
+use sneedov::markov::{Markov, MarkovType, ReplyMode};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const LINES: [&str; 5] = [
+    "the cat sat on the mat",
+    "the dog sat on the rug",
+    "a cat and a dog sat together",
+    "the mat was on the floor",
+    "a dog ate the cat food",
+];
+
+async fn seeded(seed: u64) -> Result<Markov, Error> {
+    let database = Arc::new(SqliteDB::new(std::path::Path::new(":memory:")).await?);
+    let markov = Markov::builder(database).seed(seed).build().await?;
+    for line in LINES {
+        markov.append_line(line).await?;
+    }
+    Ok(markov)
+}
+
+#[tokio::test]
+async fn the_same_seed_generates_the_same_sentences() -> Result<(), Error> {
+    let first = seeded(42).await?;
+    let second = seeded(42).await?;
+
+    for _ in 0..5 {
+        assert_eq!(first.generate().await?, second.generate().await?);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn replying_to_only_zero_width_characters_is_an_error() -> Result<(), Error> {
+    let database = Arc::new(SqliteDB::new(std::path::Path::new(":memory:")).await?);
+    let markov = Markov::builder(database)
+        .tokenizer(TokenizerType::Unicode.build())
+        .reply_mode(ReplyMode::Reply)
+        .seed(42)
+        .build()
+        .await?;
+    for line in LINES {
+        markov.append_line(line).await?;
+    }
+
+    for line in ["\u{200B}", "\u{200D}", "  "] {
+        let error = markov.generate_reply(line).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<GenerationError>(),
+            Some(GenerationError::EmptyInput)
+        ));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn a_discount_above_one_is_rejected() -> Result<(), Error> {
+    let database = Arc::new(SqliteDB::new(std::path::Path::new(":memory:")).await?);
+    let built = Markov::builder(database)
+        .markov_type(MarkovType::Interpolated(150))
+        .build()
+        .await;
+    assert!(built.is_err());
+    Ok(())
+}